@@ -1,6 +1,57 @@
+use std::{env, fmt::Write as _, fs, path::Path};
+
+// Built-in assets packed into one compressed bundle, (constant name, file)
+const BUNDLED_ASSETS: [(&str, &str); 15] = [
+    ("LEGACY_CUBEMAP_MATERIAL_BIN", "src/java_cubemap/LegacyCubemap.material.bin"),
+    ("RENDER_CHUNK_MATERIAL_BIN", "src/no_fog_materials/RenderChunk.material.bin"),
+    ("NO_FOG_ACTOR_BANNER_MATERIAL_BIN", "src/no_fog_materials/ActorBanner.material.bin"),
+    ("NO_FOG_ACTOR_GLINT_MATERIAL_BIN", "src/no_fog_materials/ActorGlint.material.bin"),
+    ("NO_FOG_ITEM_IN_HAND_COLOR_MATERIAL_BIN", "src/no_fog_materials/ItemInHandColor.material.bin"),
+    ("NO_FOG_ITEM_IN_HAND_COLOR_GLINT_MATERIAL_BIN", "src/no_fog_materials/ItemInHandColorGlint.material.bin"),
+    ("NO_FOG_ITEM_IN_HAND_TEXTURED_MATERIAL_BIN", "src/no_fog_materials/ItemInHandTextured.material.bin"),
+    ("NO_FOG_PARTICLE_MATERIAL_BIN", "src/no_fog_materials/Particle.material.bin"),
+    ("NO_FOG_LEGACY_CUBEMAP_MATERIAL_BIN", "src/no_fog_materials/LegacyCubemap.material.bin"),
+    ("RENDER_CHUNK_NV_MATERIAL_BIN", "src/nightvision_materials/RenderChunk.material.bin"),
+    ("CLASSIC_STEVE_TEXTURE", "src/s.png"),
+    ("CLASSIC_ALEX_TEXTURE", "src/a.png"),
+    ("JAVA_CLOUDS_TEXTURE", "src/Diskksks.png"),
+    ("MOBS_JSON", "src/cape_physics/mobs.json"),
+    ("PLAYER_ANIMATION_JSON", "src/cape_physics/player.animation.json"),
+];
+
 fn main() {
+    // Any rerun-if-changed turns off rebuilding on every change, so list everything
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/string.cpp");
     cc::Build::new()
         .cpp(true)
         .file("src/string.cpp")
         .compile("stringstub");
+    bundle_assets();
+}
+
+// Writes assets.bundle, every asset deflated back to back, and bundle_index.rs
+// with one constant per asset pointing into it
+fn bundle_assets() {
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    let mut bundle = Vec::new();
+    let mut index = String::new();
+    for (id, (name, file)) in BUNDLED_ASSETS.iter().enumerate() {
+        println!("cargo:rerun-if-changed={file}");
+        let data = fs::read(file).unwrap_or_else(|e| panic!("Failed to read {file}: {e}"));
+        let compressed = miniz_oxide::deflate::compress_to_vec(&data, 10);
+        writeln!(
+            index,
+            "pub const {name}: BundledAsset = BundledAsset {{ id: {id}, name: {file:?}, offset: {}, compressed_len: {}, len: {} }};",
+            bundle.len(),
+            compressed.len(),
+            data.len(),
+        )
+        .unwrap();
+        bundle.extend_from_slice(&compressed);
+    }
+    writeln!(index, "pub const BUNDLED_ASSET_COUNT: usize = {};", BUNDLED_ASSETS.len()).unwrap();
+    let out_dir = Path::new(&out_dir);
+    fs::write(out_dir.join("assets.bundle"), bundle).expect("Failed to write assets.bundle");
+    fs::write(out_dir.join("bundle_index.rs"), index).expect("Failed to write bundle_index.rs");
 }
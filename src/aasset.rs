@@ -1,50 +1,150 @@
 use crate::ResourceLocation;
-use crate::config::{is_no_hurt_cam_enabled, is_no_fog_enabled, is_java_cubemap_enabled, is_particles_disabler_enabled, is_java_clouds_enabled, is_classic_skins_enabled, is_cape_physics_enabled, is_night_vision_enabled};
+use crate::bundle::{
+    CLASSIC_ALEX_TEXTURE, CLASSIC_STEVE_TEXTURE, JAVA_CLOUDS_TEXTURE, MOBS_JSON, PLAYER_ANIMATION_JSON,
+};
+use crate::config::{get_config, ModConfig, NoHurtCamConfig};
+use crate::dump;
+use crate::fog;
+use crate::json_patch::JsonPatch;
+use crate::material_cache::{self, Cached};
+use crate::material_edits::{self, MATERIAL_EDITS};
+use crate::memfd;
+use crate::messages;
+use crate::overrides;
+use crate::particles;
+use crate::rules::{self, Feature, PathPattern, Rule, RuleAction, TransformContext};
+use crate::trace;
 use libc::{off64_t, off_t};
 use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
 use ndk::asset::Asset;
 use ndk_sys::{AAsset, AAssetManager};
 use once_cell::sync::Lazy;
 use scroll::Pread;
-use serde_json::{Value, Map};
+use serde_json::{json, Value};
 use std::{
     borrow::Cow,
     collections::HashMap,
     ffi::{CStr, CString, OsStr},
+    fs,
     io::{self, Cursor, Read, Seek, Write},
-    os::unix::ffi::OsStrExt,
+    os::{
+        fd::{OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
     path::{Path, PathBuf},
-    sync::{Mutex, OnceLock},
+    sync::{Arc, Mutex, OnceLock, RwLock},
 };
 
 #[derive(PartialEq, Eq, Hash)]
 struct AAssetPtr(*const ndk_sys::AAsset);
 unsafe impl Send for AAssetPtr {}
+unsafe impl Sync for AAssetPtr {}
 
 static MC_VERSION: OnceLock<Option<MinecraftVersion>> = OnceLock::new();
 
-static WANTED_ASSETS: Lazy<Mutex<HashMap<AAssetPtr, Cursor<Vec<u8>>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+static WANTED_ASSETS: Lazy<HandleTable> = Lazy::new(HandleTable::new);
 
-const LEGACY_CUBEMAP_MATERIAL_BIN: &[u8] = include_bytes!("java_cubemap/LegacyCubemap.material.bin");
-const RENDER_CHUNK_MATERIAL_BIN: &[u8] = include_bytes!("no_fog_materials/RenderChunk.material.bin");
+const HANDLE_SHARDS: usize = 16;
 
-const RENDER_CHUNK_NV_MATERIAL_BIN: &[u8] = include_bytes!("nightvision_materials/RenderChunk.material.bin");
+// One replaced asset, locked on its own so reads of different assets never wait on each other
+type Handle = Arc<Mutex<HandleState>>;
 
-const CUSTOM_SPLASHES_JSON: &str = r#"{"splashes":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"]}"#;
+struct HandleState {
+    cursor: Cursor<AssetData>,
+    // Made up by us because the APK doesn't have the asset, see new_synthetic_handle
+    synthetic: bool,
+    // Created the first time the game asks for a file descriptor
+    memfd: Option<OwnedFd>,
+}
 
-const CUSTOM_FIRST_PERSON_JSON: &str = r#"{"format_version":"1.18.10","minecraft:camera_entity":{"description":{"identifier":"minecraft:first_person"},"components":{"minecraft:camera":{"field_of_view":66,"near_clipping_plane":0.025,"far_clipping_plane":2500},"minecraft:camera_first_person":{},"minecraft:camera_render_first_person_objects":{},"minecraft:camera_attach_to_player":{},"minecraft:camera_offset":{"view":[0,0],"entity":[0,0,0]},"minecraft:camera_direct_look":{"pitch_min":-89.9,"pitch_max":89.9},"minecraft:camera_perspective_option":{"view_mode":"first_person"},"minecraft:update_player_from_camera":{"look_mode":"along_camera"},"minecraft:extend_player_rendering":{},"minecraft:camera_player_sleep_vignette":{},"minecraft:vr_comfort_move":{},"minecraft:default_input_camera":{},"minecraft:gameplay_affects_fov":{},"minecraft:allow_inside_block":{}}}}"#;
-const CUSTOM_THIRD_PERSON_JSON: &str = r#"{"format_version":"1.18.10","minecraft:camera_entity":{"description":{"identifier":"minecraft:third_person"},"components":{"minecraft:camera":{"field_of_view":66,"near_clipping_plane":0.025,"far_clipping_plane":2500},"minecraft:camera_third_person":{},"minecraft:camera_render_player_model":{},"minecraft:camera_attach_to_player":{},"minecraft:camera_offset":{"view":[0,0],"entity":[0,2,5]},"minecraft:camera_look_at_player":{},"minecraft:camera_orbit":{"azimuth_smoothing_spring":0,"polar_angle_smoothing_spring":0,"distance_smoothing_spring":0,"polar_angle_min":0.1,"polar_angle_max":179.9,"radius":4},"minecraft:camera_avoidance":{"relax_distance_smoothing_spring":0,"distance_constraint_min":0.25},"minecraft:camera_perspective_option":{"view_mode":"third_person"},"minecraft:update_player_from_camera":{"look_mode":"along_camera"},"minecraft:camera_player_sleep_vignette":{},"minecraft:gameplay_affects_fov":{},"minecraft:allow_inside_block":{},"minecraft:extend_player_rendering":{}}}}"#;
-const CUSTOM_THIRD_PERSON_FRONT_JSON: &str = r#"{"format_version":"1.18.10","minecraft:camera_entity":{"description":{"identifier":"minecraft:third_person_front"},"components":{"minecraft:camera":{"field_of_view":66,"near_clipping_plane":0.025,"far_clipping_plane":2500},"minecraft:camera_third_person":{},"minecraft:camera_render_player_model":{},"minecraft:camera_attach_to_player":{},"minecraft:camera_offset":{"view":[0,0],"entity":[0,2,5]},"minecraft:camera_look_at_player":{},"minecraft:camera_orbit":{"azimuth_smoothing_spring":0,"polar_angle_smoothing_spring":0,"distance_smoothing_spring":0,"polar_angle_min":0.1,"polar_angle_max":179.9,"radius":4,"invert_x_input":true},"minecraft:camera_avoidance":{"relax_distance_smoothing_spring":0,"distance_constraint_min":0.25},"minecraft:camera_perspective_option":{"view_mode":"third_person_front"},"minecraft:update_player_from_camera":{"look_mode":"at_camera"},"minecraft:camera_player_sleep_vignette":{},"minecraft:gameplay_affects_fov":{},"minecraft:allow_inside_block":{},"minecraft:extend_player_rendering":{}}}}"#;
+impl HandleState {
+    fn open_fd(&mut self) -> io::Result<RawFd> {
+        let memfd = match &self.memfd {
+            Some(memfd) => memfd,
+            None => {
+                let data = self.cursor.get_ref().as_ref();
+                self.memfd.insert(memfd::sealed_memfd(c"mtbinloader-asset", data)?)
+            }
+        };
+        memfd::open_readonly(memfd)
+    }
+}
 
-const CUSTOM_LOADING_MESSAGES_JSON: &str = r#"{"beginner_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"mid_game_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"late_game_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"creative_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"editor_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"realms_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"addons_loading_messages":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"],"store_progress_tooltips":["Origin Client","Origin > any other client","The Best Client!!","BlueCat","Origin is so much better","Origin Optimizes like no other client","Make Sure to star our repository:https://github.com/Origin-Client/Origin","Contributions open!","Made by the community, for the community","Yami is goated!!"]}"#;
+// Replaced assets by handle, sharded so loader threads opening and closing
+// different assets rarely touch the same lock
+struct HandleTable {
+    shards: [RwLock<HashMap<AAssetPtr, Handle>>; HANDLE_SHARDS],
+}
+
+impl HandleTable {
+    fn new() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| RwLock::new(HashMap::new())),
+        }
+    }
+
+    fn shard(&self, aasset: *const AAsset) -> &RwLock<HashMap<AAssetPtr, Handle>> {
+        // Handles are heap allocations, the low bits are always the same
+        &self.shards[(aasset as usize >> 4) % HANDLE_SHARDS]
+    }
+
+    fn get(&self, aasset: *const AAsset) -> Option<Handle> {
+        let shard = self.shard(aasset).read().unwrap();
+        shard.get(&AAssetPtr(aasset)).cloned()
+    }
+
+    fn contains(&self, aasset: *const AAsset) -> bool {
+        self.shard(aasset).read().unwrap().contains_key(&AAssetPtr(aasset))
+    }
+
+    fn insert(&self, aasset: *const AAsset, data: AssetData, synthetic: bool) {
+        let handle = Arc::new(Mutex::new(HandleState {
+            cursor: Cursor::new(data),
+            synthetic,
+            memfd: None,
+        }));
+        self.shard(aasset).write().unwrap().insert(AAssetPtr(aasset), handle);
+    }
+
+    fn remove(&self, aasset: *const AAsset) -> Option<Handle> {
+        // Only the thread closing a handle removes it, so the read check can't go stale
+        if !self.contains(aasset) {
+            return None;
+        }
+        self.shard(aasset).write().unwrap().remove(&AAssetPtr(aasset))
+    }
+}
+
+/// Bytes served in place of an asset, embedded ones are never copied
+pub(crate) enum AssetData {
+    Static(&'static [u8]),
+    // Read from disk, patched or converted
+    Owned(Vec<u8>),
+    // Built once and served to every open, like composed materials
+    Shared(Arc<[u8]>),
+}
+
+impl AsRef<[u8]> for AssetData {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Static(data) => data,
+            Self::Owned(data) => data,
+            Self::Shared(data) => data,
+        }
+    }
+}
 
-const CUSTOM_SKINS_JSON: &str = r#"{"skins":[{"localization_name":"Steve","geometry":"geometry.humanoid.custom","texture":"steve.png","type":"free"},{"localization_name":"Alex","geometry":"geometry.humanoid.customSlim","texture":"alex.png","type":"free"}],"serialize_name":"Standard","localization_name":"Standard"}"#;
+impl From<Vec<u8>> for AssetData {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Owned(data)
+    }
+}
 
-const CLASSIC_STEVE_TEXTURE: &[u8] = include_bytes!("s.png");
-const CLASSIC_ALEX_TEXTURE: &[u8] = include_bytes!("a.png");
+// Format of the built-in cameras, served when the vanilla one can't be patched
+const CAMERA_FORMAT_VERSION: &str = "1.18.10";
 
-const JAVA_CLOUDS_TEXTURE: &[u8] = include_bytes!("Diskksks.png");
+// Merged into the vanilla skins.json, the skins array is replaced as a whole
+const CLASSIC_SKINS_PATCH: &str = r#"{"skins":[{"localization_name":"Steve","geometry":"geometry.humanoid.custom","texture":"steve.png","type":"free"},{"localization_name":"Alex","geometry":"geometry.humanoid.customSlim","texture":"alex.png","type":"free"}]}"#;
 
 fn get_current_mcver(man: ndk::asset::AssetManager) -> Option<MinecraftVersion> {
     let mut file = match get_uitext(man) {
@@ -92,176 +192,156 @@ macro_rules! folder_list {
     }
 }
 
-fn get_no_fog_material_data(filename: &str) -> Option<&'static [u8]> {
-    if !is_no_fog_enabled() {
-        return None;
-    }
-    
-    match filename {
-        "RenderChunk.material.bin" => Some(RENDER_CHUNK_MATERIAL_BIN),
-        _ => None,
-    }
-}
+// Persona skins that show up next to Steve and Alex in the classic skin picker
+const BLOCKED_PERSONAS: [&str; 7] = [
+    "persona/08_Kai_Dcast.json",
+    "persona/07_Zuri_Dcast.json",
+    "persona/06_Efe_Dcast.json",
+    "persona/05_Makena_Dcast.json",
+    "persona/04_Sunny_Dcast.json",
+    "persona/03_Ari_Dcast.json",
+    "persona/02_ Noor_Dcast.json",
+];
 
-fn get_nightvision_material_data(filename: &str) -> Option<&'static [u8]> {
-    if !is_night_vision_enabled() {
-        return None;
-    }
-    
-    match filename {
-        "RenderChunk.material.bin" => Some(RENDER_CHUNK_NV_MATERIAL_BIN),
-        _ => None,
-    }
-}
+/// Rules for every built-in feature, earlier rules win when several match
+pub(crate) fn builtin_rules() -> Vec<Rule> {
+    use PathPattern::{Contains, FileName, Suffix};
+    let mut rules = Vec::new();
+    let mut add = |pattern, feature, action| rules.push(Rule::new(pattern, feature, action));
 
-fn get_java_cubemap_material_data(filename: &str) -> Option<&'static [u8]> {
-    if !is_java_cubemap_enabled() {
-        return None;
+    for persona in BLOCKED_PERSONAS {
+        add(Suffix(persona.into()), Some(Feature::ClassicSkins), RuleAction::Block);
     }
-    
-    match filename {
-        "LegacyCubemap.material.bin" => Some(LEGACY_CUBEMAP_MATERIAL_BIN),
-        _ => None,
-    }
-}
 
-// Enhanced particles disabler - blocks entire particles folder and all particle-related files
-fn is_particles_folder_to_block(c_path: &Path) -> bool {
-    if !is_particles_disabler_enabled() {
-        return false;
-    }
-    
-    let path_str = c_path.to_string_lossy();
-    
-    // Block any file related to particles
-    let particle_patterns = [
-        "/particles/",
-        "particles/",
-        "/particle/",
-        "particle/",
-        "_particle",
-        "particle_",
-        ".particle.",
-        "particles.",
-        "/effects/",
-        "effects/",
-        "_effect",
-        "effect_",
-        ".effect.",
-        "effects.",
-    ];
-    
-    particle_patterns.iter().any(|pattern| {
-        path_str.contains(pattern)
-    }) || path_str.starts_with("particles") || path_str.ends_with(".particle") || path_str.ends_with("_particle.json")
-}
+    // Only particle effect files, the transform decides by identifier
+    add(Contains("/particles/".into()), Some(Feature::ParticlesDisabler), RuleAction::Transform(particles::particle_transform));
 
-// Enhanced clouds detection with more patterns
-fn is_clouds_texture_file(c_path: &Path) -> bool {
-    if !is_java_clouds_enabled() {
-        return false;
-    }
-    
-    let path_str = c_path.to_string_lossy();
-    
-    let cloud_patterns = [
-        "textures/environment/clouds.png",
-        "/textures/environment/clouds.png",
-        "environment/clouds.png",
-        "/environment/clouds.png",
-        "clouds.png",
-        "textures/clouds.png",
-        "/textures/clouds.png",
-        "resource_packs/vanilla/textures/environment/clouds.png",
-        "assets/resource_packs/vanilla/textures/environment/clouds.png",
-        "vanilla/textures/environment/clouds.png",
-    ];
-    
-    cloud_patterns.iter().any(|pattern| {
-        path_str.contains(pattern) || path_str.ends_with(pattern)
-    })
-}
+    // Any version's fog settings, the transform only touches objects with fog distances
+    add(Contains("/fogs/".into()), Some(Feature::NoFogDefinitions), RuleAction::Transform(fog::fog_definitions_transform));
+    add(FileName("biomes_client.json".into()), Some(Feature::NoFogDefinitions), RuleAction::Transform(fog::fog_definitions_transform));
 
-fn is_skin_file_path(c_path: &Path, filename: &str) -> bool {
-    let path_str = c_path.to_string_lossy();
-    
-    let possible_paths = [
-        format!("vanilla/{}", filename),
-        format!("skin_packs/vanilla/{}", filename),
-        format!("resource_packs/vanilla/{}", filename),
-        format!("assets/skin_packs/vanilla/{}", filename),
-    ];
-    
-    possible_paths.iter().any(|path| {
-        path_str.contains(path) || path_str.ends_with(path)
-    })
-}
+    add(FileName("splashes.json".into()), Some(Feature::Splashes), RuleAction::Transform(messages::splashes_transform));
+    add(FileName("loading_messages.json".into()), Some(Feature::LoadingMessages), RuleAction::Transform(messages::loading_messages_transform));
+
+    add(FileName("clouds.png".into()), Some(Feature::JavaClouds), RuleAction::Replace(JAVA_CLOUDS_TEXTURE));
 
-fn is_classic_skins_steve_texture_file(c_path: &Path) -> bool {
-    if !is_classic_skins_enabled() {
-        return false;
+    // Covers both skin_packs/vanilla and resource_packs/vanilla
+    add(Suffix("vanilla/steve.png".into()), Some(Feature::ClassicSkins), RuleAction::Replace(CLASSIC_STEVE_TEXTURE));
+    add(Suffix("vanilla/alex.png".into()), Some(Feature::ClassicSkins), RuleAction::Replace(CLASSIC_ALEX_TEXTURE));
+    let skins_patch = serde_json::from_str(CLASSIC_SKINS_PATCH).expect("classic skins patch is valid JSON");
+    add(Suffix("vanilla/skins.json".into()), Some(Feature::ClassicSkins), RuleAction::Patch(JsonPatch::MergePatch(skins_patch)));
+
+    for camera in ["first_person.json", "third_person.json", "third_person_front.json"] {
+        add(Suffix(format!("cameras/{camera}")), Some(Feature::NoHurtCam), RuleAction::Transform(camera_transform));
     }
-    
-    is_skin_file_path(c_path, "steve.png")
-}
 
-fn is_classic_skins_alex_texture_file(c_path: &Path) -> bool {
-    if !is_classic_skins_enabled() {
-        return false;
+    // Whichever enabled edit comes first matches, the transform combines all of them
+    for edit in &MATERIAL_EDITS {
+        add(FileName(edit.material.into()), Some(edit.feature), RuleAction::Transform(material_edits::compose_transform));
     }
-    
-    is_skin_file_path(c_path, "alex.png")
+
+    add(FileName("player.animation.json".into()), Some(Feature::CapePhysics), RuleAction::Replace(PLAYER_ANIMATION_JSON));
+    add(FileName("mobs.json".into()), Some(Feature::CapePhysics), RuleAction::Replace(MOBS_JSON));
+
+    rules
 }
 
-fn is_classic_skins_json_file(c_path: &Path) -> bool {
-    if !is_classic_skins_enabled() {
-        return false;
-    }
-    
-    is_skin_file_path(c_path, "skins.json")
+fn camera_component(camera: &NoHurtCamConfig) -> Value {
+    json!({
+        "field_of_view": camera.fov,
+        "near_clipping_plane": camera.near_clipping_plane,
+        "far_clipping_plane": camera.far_clipping_plane
+    })
 }
 
-fn is_persona_file_to_block(c_path: &Path) -> bool {
-    if !is_classic_skins_enabled() {
-        return false;
-    }
-    
-    let path_str = c_path.to_string_lossy();
-    
-    let blocked_personas = [
-        "persona/08_Kai_Dcast.json",
-        "persona/07_Zuri_Dcast.json", 
-        "persona/06_Efe_Dcast.json",
-        "persona/05_Makena_Dcast.json",
-        "persona/04_Sunny_Dcast.json",
-        "persona/03_Ari_Dcast.json",
-        "persona/02_ Noor_Dcast.json", 
-    ];
-    
-    blocked_personas.iter().any(|persona_path| {
-        path_str.contains(persona_path) || path_str.ends_with(persona_path)
+fn first_person_camera_json(camera: &NoHurtCamConfig) -> Value {
+    json!({
+        "format_version": CAMERA_FORMAT_VERSION,
+        "minecraft:camera_entity": {
+            "description": { "identifier": "minecraft:first_person" },
+            "components": {
+                "minecraft:camera": camera_component(camera),
+                "minecraft:camera_first_person": {},
+                "minecraft:camera_render_first_person_objects": {},
+                "minecraft:camera_attach_to_player": {},
+                "minecraft:camera_offset": { "view": [0, 0], "entity": [0, 0, 0] },
+                "minecraft:camera_direct_look": { "pitch_min": -89.9, "pitch_max": 89.9 },
+                "minecraft:camera_perspective_option": { "view_mode": "first_person" },
+                "minecraft:update_player_from_camera": { "look_mode": "along_camera" },
+                "minecraft:extend_player_rendering": {},
+                "minecraft:camera_player_sleep_vignette": {},
+                "minecraft:vr_comfort_move": {},
+                "minecraft:default_input_camera": {},
+                "minecraft:gameplay_affects_fov": {},
+                "minecraft:allow_inside_block": {}
+            }
+        }
     })
 }
 
-fn get_cape_model_data(filename: &str) -> Option<&'static [u8]> {
-    if !is_cape_physics_enabled() {
-        return None;
-    }
-    
-    match filename {
-        "mobs.json" => Some(MOBS_JSON),
-        _ => None,
-    }
+// The front camera is the back one looking at the player with inverted x input
+fn third_person_camera_json(camera: &NoHurtCamConfig, front: bool) -> Value {
+    let (view_mode, look_mode) = match front {
+        true => ("third_person_front", "at_camera"),
+        false => ("third_person", "along_camera"),
+    };
+    let mut orbit = json!({
+        "azimuth_smoothing_spring": 0,
+        "polar_angle_smoothing_spring": 0,
+        "distance_smoothing_spring": 0,
+        "polar_angle_min": 0.1,
+        "polar_angle_max": 179.9,
+        "radius": camera.third_person_radius
+    });
+    if front {
+        orbit["invert_x_input"] = Value::Bool(true);
+    }
+    json!({
+        "format_version": CAMERA_FORMAT_VERSION,
+        "minecraft:camera_entity": {
+            "description": { "identifier": format!("minecraft:{view_mode}") },
+            "components": {
+                "minecraft:camera": camera_component(camera),
+                "minecraft:camera_third_person": {},
+                "minecraft:camera_render_player_model": {},
+                "minecraft:camera_attach_to_player": {},
+                "minecraft:camera_offset": { "view": [0, 0], "entity": [0, 2, 5] },
+                "minecraft:camera_look_at_player": {},
+                "minecraft:camera_orbit": orbit,
+                "minecraft:camera_avoidance": {
+                    "relax_distance_smoothing_spring": 0,
+                    "distance_constraint_min": 0.25
+                },
+                "minecraft:camera_perspective_option": { "view_mode": view_mode },
+                "minecraft:update_player_from_camera": { "look_mode": look_mode },
+                "minecraft:camera_player_sleep_vignette": {},
+                "minecraft:gameplay_affects_fov": {},
+                "minecraft:allow_inside_block": {},
+                "minecraft:extend_player_rendering": {}
+            }
+        }
+    })
 }
 
-fn get_cape_animation_data(filename: &str) -> Option<&'static [u8]> {
-    if !is_cape_physics_enabled() {
-        return None;
-    }
-    
-    match filename {
-        "player.animation.json" => Some(PLAYER_ANIMATION_JSON),
-        _ => None,
+fn camera_transform(ctx: &TransformContext) -> Option<AssetData> {
+    let camera = &ctx.config.no_hurt_cam;
+    let camera_json = match ctx.path.file_name()?.as_bytes() {
+        b"first_person.json" => first_person_camera_json(camera),
+        b"third_person.json" => third_person_camera_json(camera, false),
+        b"third_person_front.json" => third_person_camera_json(camera, true),
+        _ => return None,
+    };
+    // Only our components go over the vanilla camera, the rest of it is kept as is
+    let components = &camera_json["minecraft:camera_entity"]["components"];
+    let patch = JsonPatch::MergePatch(json!({
+        "minecraft:camera_entity": { "components": components }
+    }));
+    match ctx.patch_original(&patch) {
+        Some(patched) => Some(patched.into()),
+        None => {
+            log::warn!("Serving the built-in {} instead of patching it", ctx.path.display());
+            Some(camera_json.to_string().into_bytes().into())
+        }
     }
 }
 
@@ -271,166 +351,49 @@ pub(crate) unsafe fn open(
     mode: libc::c_int,
 ) -> *mut ndk_sys::AAsset {
     let aasset = unsafe { ndk_sys::AAssetManager_open(man, fname, mode) };
-    let c_str = unsafe { CStr::from_ptr(fname) };
-    let raw_cstr = c_str.to_bytes();
+    let raw_cstr = unsafe { CStr::from_ptr(fname) }.to_bytes();
+    let config = get_config();
+    let (served, handler) = intercept(man, aasset, raw_cstr, mode, &config);
+    if config.trace.enabled {
+        trace::record(&config.trace, raw_cstr, mode, !aasset.is_null(), handler);
+    }
+    served
+}
+
+// Returns the asset to hand to the game and what intercepted it, None if nothing did
+unsafe fn intercept(
+    man: *mut AAssetManager,
+    aasset: *mut AAsset,
+    raw_cstr: &[u8],
+    mode: libc::c_int,
+    config: &ModConfig,
+) -> (*mut AAsset, Option<&'static str>) {
     let os_str = OsStr::from_bytes(raw_cstr);
     let c_path: &Path = Path::new(os_str);
     
     let Some(os_filename) = c_path.file_name() else {
         log::warn!("Path had no filename: {c_path:?}");
-        return aasset;
+        return (aasset, None);
     };
 
-    // Debug logging for features
-    
-    if is_particles_disabler_enabled() {
-        let path_str = c_path.to_string_lossy();
-        if path_str.contains("particle") || path_str.contains("effect") {
-            log::info!("Particles disabler enabled - checking file: {}", c_path.display());
-        }
-    }
-
-    // Block persona files if classic skins enabled
-    if is_persona_file_to_block(c_path) {
-        log::info!("Blocking persona file due to classic_skins enabled: {}", c_path.display());
-        if !aasset.is_null() {
-            ndk_sys::AAsset_close(aasset);
-        }
-        return std::ptr::null_mut();
-    }
-
-    // Block entire particles folder if particles disabler enabled
-    if is_particles_folder_to_block(c_path) {
-        log::info!("Blocking particles file due to particles_disabler enabled: {}", c_path.display());
-        if !aasset.is_null() {
-            ndk_sys::AAsset_close(aasset);
-        }
-        return std::ptr::null_mut();
-    }
-    
-    // Custom splashes
-    if os_filename == "splashes.json" {
-        log::info!("Intercepting splashes.json with custom content");
-        let buffer = CUSTOM_SPLASHES_JSON.as_bytes().to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    // Custom loading messages
-    if os_filename == "loading_messages.json" {
-        log::info!("Intercepting loading_messages.json with custom content");
-        let buffer = CUSTOM_LOADING_MESSAGES_JSON.as_bytes().to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    // Java clouds texture replacement
-    if is_clouds_texture_file(c_path) {
-        log::info!("Intercepting clouds texture with Java clouds texture: {}", c_path.display());
-        let buffer = JAVA_CLOUDS_TEXTURE.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-
-    // Classic skins replacements
-    if is_classic_skins_steve_texture_file(c_path) {
-        log::info!("Intercepting steve.png with classic Steve texture: {}", c_path.display());
-        let buffer = CLASSIC_STEVE_TEXTURE.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    if is_classic_skins_alex_texture_file(c_path) {
-        log::info!("Intercepting alex.png with classic Alex texture: {}", c_path.display());
-        let buffer = CLASSIC_ALEX_TEXTURE.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    if is_classic_skins_json_file(c_path) {
-        log::info!("Intercepting skins.json with classic skins content: {}", c_path.display());
-        let buffer = CUSTOM_SKINS_JSON.as_bytes().to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    // No hurt cam camera replacements
-    if is_no_hurt_cam_enabled() {
-        let path_str = c_path.to_string_lossy();
-        
-        if path_str.contains("cameras/") {
-            if os_filename == "first_person.json" {
-                log::info!("Intercepting cameras/first_person.json with custom content (nohurtcam enabled)");
-                let buffer = CUSTOM_FIRST_PERSON_JSON.as_bytes().to_vec();
-                let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-                wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-                return aasset;
-            }
-            
-            if os_filename == "third_person.json" {
-                log::info!("Intercepting cameras/third_person.json with custom content (nohurtcam enabled)");
-                let buffer = CUSTOM_THIRD_PERSON_JSON.as_bytes().to_vec();
-                let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-                wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-                return aasset;
-            }
-            
-            if os_filename == "third_person_front.json" {
-                log::info!("Intercepting cameras/third_person_front.json with custom content (nohurtcam enabled)");
-                let buffer = CUSTOM_THIRD_PERSON_FRONT_JSON.as_bytes().to_vec();
-                let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-                wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-                return aasset;
+    // User overrides shadow everything else
+    if let Some(file) = overrides::find_override(raw_cstr) {
+        match fs::read(&file) {
+            Ok(buffer) => {
+                log::info!("Intercepting {} with override {}", c_path.display(), file.display());
+                let served = serve(aasset, c_path, buffer.into(), config, true);
+                return (served, Some("override"));
             }
+            Err(e) => log::warn!("Failed to read override {}: {e}", file.display()),
         }
     }
 
-    // Material replacements
-    let filename_str = os_filename.to_string_lossy();
-    if let Some(no_fog_data) = get_no_fog_material_data(&filename_str) {
-        log::info!("Intercepting {} with no-fog material (no-fog enabled)", filename_str);
-        let buffer = no_fog_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    if let Some(night_vision_data) = get_nightvision_material_data(&filename_str) {
-        log::info!("Intercepting {} with night-vision material (night-vision enabled)", filename_str);
-        let buffer = night_vision_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    if let Some(cape_physics_animation_data) = get_cape_animation_data(&filename_str) {
-        log::info!("Intercepting {} with cape-physics animation (cape-physics enabled)", filename_str);
-        let buffer = cape_physics_animation_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    if let Some(cape_physics_model_data) = get_cape_model_data(&filename_str) {
-        log::info!("Intercepting {} with cape-physics model (cape-physics enabled)", filename_str);
-        let buffer = cape_physics_model_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
-    }
-    
-    if let Some(java_cubemap_data) = get_java_cubemap_material_data(&filename_str) {
-        log::info!("Intercepting {} with java-cubemap material (java-cubemap enabled)", filename_str);
-        let buffer = java_cubemap_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
+    let rules = rules::current_rules();
+    if let Some(rule) = rules.find(raw_cstr) {
+        return match apply_rule(man, aasset, c_path, mode, rule, config) {
+            Some(served) => (served, Some(rule.label())),
+            None => (aasset, None),
+        };
     }
 
     // Resource pack loading logic
@@ -453,7 +416,7 @@ pub(crate) unsafe fn open(
                 Some(ptr) => ptr,
                 None => {
                     log::warn!("ResourcePackManager fn is not ready yet?");
-                    return aasset;
+                    return (aasset, None);
                 }
             };
             let mut arraybuf = [0; 128];
@@ -463,27 +426,161 @@ pub(crate) unsafe fn open(
             log::info!("loading rpck file: {:#?}", &file_path);
             if packm_ptr.is_null() {
                 log::error!("ResourcePackManager ptr is null");
-                return aasset;
+                return (aasset, None);
             }
             loadfn(packm_ptr, resource_loc, cxx_out.as_mut());
             if cxx_out.is_empty() {
                 log::info!("File was not found");
-                return aasset;
+                return (aasset, None);
             }
             let buffer = if os_filename.as_encoded_bytes().ends_with(b".material.bin") {
-                match process_material(man, cxx_out.as_bytes()) {
-                    Some(updated) => updated,
-                    None => cxx_out.as_bytes().to_vec(),
+                let hash = || Some(material_cache::hash_material(cxx_out.as_bytes()));
+                match process_material(man, cxx_out.as_bytes(), hash, config) {
+                    MaterialConversion::Converted(updated) => updated,
+                    MaterialConversion::Unchanged | MaterialConversion::Failed => {
+                        cxx_out.as_bytes().to_vec()
+                    }
                 }
             } else {
                 cxx_out.as_bytes().to_vec()
             };
-            let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-            return aasset;
+            let served = serve(aasset, c_path, buffer.into(), config, false);
+            return (served, (!served.is_null()).then_some("resource_pack"));
         }
     }
-    return aasset;
+    return (aasset, None);
+}
+
+// None when the rule declined and the original should be served untouched
+unsafe fn apply_rule(
+    man: *mut AAssetManager,
+    aasset: *mut AAsset,
+    c_path: &Path,
+    mode: libc::c_int,
+    rule: &Rule,
+    config: &ModConfig,
+) -> Option<*mut AAsset> {
+    let label = rule.label();
+    // Only user files may add assets the APK lacks, built-in rules would make up files
+    // in every pack the game probes, e.g. splashes.json in the versioned vanilla packs
+    let inject = matches!(rule.action, RuleAction::ReplaceFile(_));
+    if aasset.is_null() && !inject && !matches!(rule.action, RuleAction::Block | RuleAction::Redirect(_)) {
+        return None;
+    }
+    let buffer = match &rule.action {
+        RuleAction::Block => {
+            log::info!("Blocking {} ({label})", c_path.display());
+            if !aasset.is_null() {
+                ndk_sys::AAsset_close(aasset);
+            }
+            return Some(std::ptr::null_mut());
+        }
+        RuleAction::Redirect(target) => {
+            let redirected = ndk_sys::AAssetManager_open(man, target.as_ptr(), mode);
+            if redirected.is_null() {
+                log::warn!("Redirect target {target:?} for {} does not exist ({label})", c_path.display());
+                return None;
+            }
+            log::info!("Redirecting {} to {target:?} ({label})", c_path.display());
+            // Never goes through serve, the game gets the target's real handle
+            if config.dump.enabled {
+                if let Some(served) = read_original(redirected) {
+                    dump::dump_asset(c_path, read_original(aasset).as_deref(), &served);
+                }
+            }
+            if !aasset.is_null() {
+                ndk_sys::AAsset_close(aasset);
+            }
+            return Some(redirected);
+        }
+        RuleAction::Replace(asset) => AssetData::Static(asset.data()?),
+        RuleAction::ReplaceFile(file) => match fs::read(file) {
+            Ok(data) => data.into(),
+            Err(e) => {
+                log::warn!("Failed to read replacement {} ({label}): {e}", file.display());
+                return None;
+            }
+        },
+        RuleAction::Patch(patch) => {
+            let ctx = TransformContext {
+                config,
+                path: c_path,
+                manager: man,
+                asset: aasset,
+            };
+            ctx.patch_original(patch)?.into()
+        }
+        RuleAction::Transform(transform) => {
+            let ctx = TransformContext {
+                config,
+                path: c_path,
+                manager: man,
+                asset: aasset,
+            };
+            transform(&ctx)?
+        }
+    };
+    log::info!("Intercepting {} ({label})", c_path.display());
+    Some(serve(aasset, c_path, buffer, config, inject))
+}
+
+// Every replaced asset goes through here, so the dump sees exactly what the game gets.
+// Returns the handle to give the game. If the APK doesn't have the asset that's a
+// synthetic one when inject is set, and null otherwise
+unsafe fn serve(
+    aasset: *mut AAsset,
+    c_path: &Path,
+    buffer: AssetData,
+    config: &ModConfig,
+    inject: bool,
+) -> *mut AAsset {
+    if aasset.is_null() && !inject {
+        return aasset;
+    }
+    if config.dump.enabled {
+        let original = read_original(aasset);
+        dump::dump_asset(c_path, original.as_deref(), buffer.as_ref());
+    }
+    if !aasset.is_null() {
+        WANTED_ASSETS.insert(aasset, buffer, false);
+        return aasset;
+    }
+    log::info!("{} is not in the APK, injecting it", c_path.display());
+    let synthetic = new_synthetic_handle();
+    WANTED_ASSETS.insert(synthetic, buffer, true);
+    synthetic
+}
+
+// Only our hooks ever see these, a real allocation keeps them from colliding with real handles
+fn new_synthetic_handle() -> *mut AAsset {
+    Box::into_raw(Box::new(0u8)).cast()
+}
+
+unsafe fn free_synthetic_handle(aasset: *mut AAsset) {
+    drop(Box::from_raw(aasset.cast::<u8>()));
+}
+
+/// Reads a real asset through the real AAsset_read, then rewinds it
+pub(crate) unsafe fn read_original(aasset: *mut AAsset) -> Option<Vec<u8>> {
+    if aasset.is_null() {
+        return None;
+    }
+    let length = ndk_sys::AAsset_getLength64(aasset);
+    let mut buffer = Vec::with_capacity(usize::try_from(length).unwrap_or(0));
+    let mut chunk = [0u8; 16 * 1024];
+    loop {
+        let read = ndk_sys::AAsset_read(aasset, chunk.as_mut_ptr().cast(), chunk.len());
+        match read {
+            0 => break,
+            1.. => buffer.extend_from_slice(&chunk[..read as usize]),
+            _ => {
+                log::warn!("Reading the original asset failed");
+                return None;
+            }
+        }
+    }
+    ndk_sys::AAsset_seek64(aasset, 0, libc::SEEK_SET);
+    Some(buffer)
 }
 
 fn opt_path_join<'a>(bytes: &'a mut [u8; 128], paths: &[&Path]) -> Cow<'a, CStr> {
@@ -507,8 +604,9 @@ fn opt_path_join<'a>(bytes: &'a mut [u8; 128], paths: &[&Path]) -> Cow<'a, CStr>
     Cow::Borrowed(guh)
 }
 
-fn process_material(man: *mut AAssetManager, data: &[u8]) -> Option<Vec<u8>> {
-    let mcver = MC_VERSION.get_or_init(|| {
+/// The running game's material version, detected once from UIText.material.bin
+pub(crate) fn mc_version(man: *mut AAssetManager) -> Option<MinecraftVersion> {
+    *MC_VERSION.get_or_init(|| {
         let pointer = match std::ptr::NonNull::new(man) {
             Some(yay) => yay,
             None => {
@@ -518,46 +616,95 @@ fn process_material(man: *mut AAssetManager, data: &[u8]) -> Option<Vec<u8>> {
         };
         let manager = unsafe { ndk::asset::AssetManager::from_ptr(pointer) };
         get_current_mcver(manager)
-    });
-    let mcver = (*mcver)?;
-    for version in materialbin::ALL_VERSIONS {
-        let material: CompiledMaterialDefinition = match data.pread_with(0, version) {
-            Ok(data) => data,
-            Err(e) => {
-                log::trace!("[version] Parsing failed: {e}");
-                continue;
-            }
-        };
-        if version == mcver {
-            return None;
-        }
-        let mut output = Vec::with_capacity(data.len());
-        if let Err(e) = material.write(&mut output, mcver) {
-            log::trace!("[version] Write error: {e}");
-            return None;
+    })
+}
+
+pub(crate) enum MaterialConversion {
+    // Already in the game's material version
+    Unchanged,
+    Converted(Vec<u8>),
+    // The game's version is unknown, or no materialbin version can read it
+    Failed,
+}
+
+// hash gives data's material_cache::hash_material and is only called when the cache
+// is on. Callers serving the same bytes over and over keep it around instead of
+// hashing on every open, None skips the cache
+pub(crate) fn process_material(
+    man: *mut AAssetManager,
+    data: &[u8],
+    hash: impl FnOnce() -> Option<u64>,
+    config: &ModConfig,
+) -> MaterialConversion {
+    let Some(mcver) = mc_version(man) else {
+        return MaterialConversion::Failed;
+    };
+    let cache_config = &config.material_cache;
+    let hash = if cache_config.enabled { hash() } else { None };
+    if let Some(hash) = hash {
+        match material_cache::lookup(hash, mcver) {
+            Some(Cached::Unchanged) => return MaterialConversion::Unchanged,
+            Some(Cached::Converted(output)) => return MaterialConversion::Converted(output),
+            None => {}
         }
-        return Some(output);
     }
+    let Some((source, output)) = convert_material(data, mcver) else {
+        return MaterialConversion::Failed;
+    };
+    if let Some(hash) = hash {
+        material_cache::store(cache_config, hash, source, mcver, output.as_deref());
+    }
+    match output {
+        Some(output) => MaterialConversion::Converted(output),
+        None => MaterialConversion::Unchanged,
+    }
+}
+
+// None if no version could parse it, otherwise its version and the converted
+// material, which is None when it already was in the target version
+fn convert_material(
+    data: &[u8],
+    mcver: MinecraftVersion,
+) -> Option<(MinecraftVersion, Option<Vec<u8>>)> {
+    let (version, material) = parse_material(data)?;
+    if version == mcver {
+        return Some((version, None));
+    }
+    let mut output = Vec::with_capacity(data.len());
+    if let Err(e) = material.write(&mut output, mcver) {
+        log::trace!("[version] Write error: {e}");
+        return None;
+    }
+    Some((version, Some(output)))
+}
 
+/// Parses a material with the first version that can read it
+pub(crate) fn parse_material(data: &[u8]) -> Option<(MinecraftVersion, CompiledMaterialDefinition)> {
+    for version in materialbin::ALL_VERSIONS {
+        match data.pread_with(0, version) {
+            Ok(material) => return Some((version, material)),
+            Err(e) => log::trace!("[version] Parsing failed: {e}"),
+        }
+    }
     None
 }
 
 pub(crate) unsafe fn seek64(aasset: *mut AAsset, off: off64_t, whence: libc::c_int) -> off64_t {
-    let mut wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get_mut(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_seek64(aasset, off, whence),
     };
-    seek_facade(off, whence, file) as off64_t
+    let mut file = file.lock().unwrap();
+    seek_facade(off, whence, &mut file.cursor) as off64_t
 }
 
 pub(crate) unsafe fn seek(aasset: *mut AAsset, off: off_t, whence: libc::c_int) -> off_t {
-    let mut wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get_mut(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_seek(aasset, off, whence),
     };
-    seek_facade(off.into(), whence, file) as off_t
+    let mut file = file.lock().unwrap();
+    seek_facade(off.into(), whence, &mut file.cursor) as off_t
 }
 
 pub(crate) unsafe fn read(
@@ -565,13 +712,13 @@ pub(crate) unsafe fn read(
     buf: *mut libc::c_void,
     count: libc::size_t,
 ) -> libc::c_int {
-    let mut wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get_mut(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_read(aasset, buf, count),
     };
+    let mut file = file.lock().unwrap();
     let rs_buffer = core::slice::from_raw_parts_mut(buf as *mut u8, count);
-    let read_total = match file.read(rs_buffer) {
+    let read_total = match file.cursor.read(rs_buffer) {
         Ok(n) => n,
         Err(e) => {
             log::warn!("failed fake aaset read: {e}");
@@ -582,55 +729,61 @@ pub(crate) unsafe fn read(
 }
 
 pub(crate) unsafe fn len(aasset: *mut AAsset) -> off_t {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_getLength(aasset),
     };
-    file.get_ref().len() as off_t
+    let file = file.lock().unwrap();
+    file.cursor.get_ref().as_ref().len() as off_t
 }
 
 pub(crate) unsafe fn len64(aasset: *mut AAsset) -> off64_t {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_getLength64(aasset),
     };
-    file.get_ref().len() as off64_t
+    let file = file.lock().unwrap();
+    file.cursor.get_ref().as_ref().len() as off64_t
 }
 
 pub(crate) unsafe fn rem(aasset: *mut AAsset) -> off_t {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_getRemainingLength(aasset),
     };
-    (file.get_ref().len() - file.position() as usize) as off_t
+    let file = file.lock().unwrap();
+    (file.cursor.get_ref().as_ref().len() - file.cursor.position() as usize) as off_t
 }
 
 pub(crate) unsafe fn rem64(aasset: *mut AAsset) -> off64_t {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_getRemainingLength64(aasset),
     };
-    (file.get_ref().len() - file.position() as usize) as off64_t
+    let file = file.lock().unwrap();
+    (file.cursor.get_ref().as_ref().len() - file.cursor.position() as usize) as off64_t
 }
 
 pub(crate) unsafe fn close(aasset: *mut AAsset) {
-    let mut wanted_assets = WANTED_ASSETS.lock().unwrap();
-    if wanted_assets.remove(&AAssetPtr(aasset)).is_none() {
-        ndk_sys::AAsset_close(aasset);
+    let synthetic = match WANTED_ASSETS.remove(aasset) {
+        Some(file) => file.lock().unwrap().synthetic,
+        None => false,
+    };
+    // A replaced APK asset is still open on the real side
+    match synthetic {
+        true => free_synthetic_handle(aasset),
+        false => ndk_sys::AAsset_close(aasset),
     }
 }
 
 pub(crate) unsafe fn get_buffer(aasset: *mut AAsset) -> *const libc::c_void {
-    let mut wanted_assets = WANTED_ASSETS.lock().unwrap();
-    let file = match wanted_assets.get_mut(&AAssetPtr(aasset)) {
+    let file = match WANTED_ASSETS.get(aasset) {
         Some(file) => file,
         None => return ndk_sys::AAsset_getBuffer(aasset),
     };
-    file.get_mut().as_mut_ptr().cast()
+    // The data stays put until close drops the handle
+    let file = file.lock().unwrap();
+    file.cursor.get_ref().as_ref().as_ptr().cast()
 }
 
 pub(crate) unsafe fn fd_dummy(
@@ -638,14 +791,30 @@ pub(crate) unsafe fn fd_dummy(
     out_start: *mut off_t,
     out_len: *mut off_t,
 ) -> libc::c_int {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    match wanted_assets.get(&AAssetPtr(aasset)) {
-        Some(_) => {
-            log::error!("WE GOT BUSTED NOOO");
-            -1
+    let file = match WANTED_ASSETS.get(aasset) {
+        Some(file) => file,
+        None => return ndk_sys::AAsset_openFileDescriptor(aasset, out_start, out_len),
+    };
+    let mut file = file.lock().unwrap();
+    let (fd, length) = match memfd_for(&mut file) {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    let length = match off_t::try_from(length) {
+        Ok(length) => length,
+        Err(e) => {
+            log::error!("Replaced asset is too big for AAsset_openFileDescriptor: {e}");
+            libc::close(fd);
+            return -1;
         }
-        None => ndk_sys::AAsset_openFileDescriptor(aasset, out_start, out_len),
+    };
+    if !out_start.is_null() {
+        *out_start = 0;
     }
+    if !out_len.is_null() {
+        *out_len = length;
+    }
+    fd
 }
 
 pub(crate) unsafe fn fd_dummy64(
@@ -653,25 +822,43 @@ pub(crate) unsafe fn fd_dummy64(
     out_start: *mut off64_t,
     out_len: *mut off64_t,
 ) -> libc::c_int {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    match wanted_assets.get(&AAssetPtr(aasset)) {
-        Some(_) => {
-            log::error!("WE GOT BUSTED NOOO");
-            -1
+    let file = match WANTED_ASSETS.get(aasset) {
+        Some(file) => file,
+        None => return ndk_sys::AAsset_openFileDescriptor64(aasset, out_start, out_len),
+    };
+    let mut file = file.lock().unwrap();
+    let (fd, length) = match memfd_for(&mut file) {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    if !out_start.is_null() {
+        *out_start = 0;
+    }
+    if !out_len.is_null() {
+        *out_len = length as off64_t;
+    }
+    fd
+}
+
+// The replaced data starts at offset 0 of the memfd and spans all of it
+fn memfd_for(file: &mut HandleState) -> Option<(RawFd, usize)> {
+    match file.open_fd() {
+        Ok(fd) => Some((fd, file.cursor.get_ref().as_ref().len())),
+        Err(e) => {
+            log::error!("Failed to back a replaced asset with a memfd: {e}");
+            None
         }
-        None => ndk_sys::AAsset_openFileDescriptor64(aasset, out_start, out_len),
     }
 }
 
 pub(crate) unsafe fn is_alloc(aasset: *mut AAsset) -> libc::c_int {
-    let wanted_assets = WANTED_ASSETS.lock().unwrap();
-    match wanted_assets.get(&AAssetPtr(aasset)) {
-        Some(_) => false as libc::c_int,
-        None => ndk_sys::AAsset_isAllocated(aasset),
+    match WANTED_ASSETS.contains(aasset) {
+        true => false as libc::c_int,
+        false => ndk_sys::AAsset_isAllocated(aasset),
     }
 }
 
-fn seek_facade(offset: i64, whence: libc::c_int, file: &mut Cursor<Vec<u8>>) -> i64 {
+fn seek_facade(offset: i64, whence: libc::c_int, file: &mut Cursor<AssetData>) -> i64 {
     let offset = match whence {
         libc::SEEK_SET => {
             let u64_off = match u64::try_from(offset) {
@@ -0,0 +1,101 @@
+use crate::overrides;
+use ndk_sys::{AAssetDir, AAssetManager};
+use once_cell::sync::Lazy;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString},
+    sync::Mutex,
+};
+
+#[derive(PartialEq, Eq, Hash)]
+struct AAssetDirPtr(*const AAssetDir);
+unsafe impl Send for AAssetDirPtr {}
+
+// Listings aren't on a hot path, so one lock is fine here
+static OPEN_DIRS: Lazy<Mutex<HashMap<AAssetDirPtr, DirState>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Only dirs with override files in them get tracked
+struct DirState {
+    // Listed after the real entries, kept alive until the dir is closed
+    injected: Vec<CString>,
+    next_injected: usize,
+    real_done: bool,
+    // Real entries listed so far, so overrides of APK files aren't listed twice
+    seen: HashSet<Vec<u8>>,
+    // The APK doesn't have this dir at all
+    synthetic: bool,
+}
+
+pub(crate) unsafe fn open_dir(
+    man: *mut AAssetManager,
+    dir_name: *const libc::c_char,
+) -> *mut AAssetDir {
+    let dir = ndk_sys::AAssetManager_openDir(man, dir_name);
+    let injected = overrides::files_in_dir(CStr::from_ptr(dir_name).to_bytes());
+    if injected.is_empty() {
+        return dir;
+    }
+    let synthetic = dir.is_null();
+    let handle = match synthetic {
+        true => Box::into_raw(Box::new(0u8)).cast(),
+        false => dir,
+    };
+    let state = DirState {
+        injected,
+        next_injected: 0,
+        real_done: synthetic,
+        seen: HashSet::new(),
+        synthetic,
+    };
+    OPEN_DIRS.lock().unwrap().insert(AAssetDirPtr(handle), state);
+    handle
+}
+
+pub(crate) unsafe fn next_file_name(dir: *mut AAssetDir) -> *const libc::c_char {
+    let mut open_dirs = OPEN_DIRS.lock().unwrap();
+    let state = match open_dirs.get_mut(&AAssetDirPtr(dir)) {
+        Some(state) => state,
+        None => {
+            drop(open_dirs);
+            return ndk_sys::AAssetDir_getNextFileName(dir);
+        }
+    };
+    if !state.real_done {
+        let name = ndk_sys::AAssetDir_getNextFileName(dir);
+        if !name.is_null() {
+            state.seen.insert(CStr::from_ptr(name).to_bytes().to_vec());
+            return name;
+        }
+        state.real_done = true;
+    }
+    while let Some(name) = state.injected.get(state.next_injected) {
+        state.next_injected += 1;
+        if !state.seen.contains(name.as_bytes()) {
+            return name.as_ptr();
+        }
+    }
+    std::ptr::null()
+}
+
+pub(crate) unsafe fn rewind(dir: *mut AAssetDir) {
+    let mut open_dirs = OPEN_DIRS.lock().unwrap();
+    if let Some(state) = open_dirs.get_mut(&AAssetDirPtr(dir)) {
+        state.next_injected = 0;
+        state.real_done = state.synthetic;
+        state.seen.clear();
+        if state.synthetic {
+            return;
+        }
+    }
+    drop(open_dirs);
+    ndk_sys::AAssetDir_rewind(dir);
+}
+
+pub(crate) unsafe fn close(dir: *mut AAssetDir) {
+    let state = OPEN_DIRS.lock().unwrap().remove(&AAssetDirPtr(dir));
+    match state {
+        Some(state) if state.synthetic => drop(Box::from_raw(dir.cast::<u8>())),
+        _ => ndk_sys::AAssetDir_close(dir),
+    }
+}
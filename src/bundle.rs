@@ -0,0 +1,54 @@
+use crate::material_cache;
+use std::sync::OnceLock;
+
+/// One built-in asset in the compressed bundle build.rs generates
+#[derive(Debug, Clone, Copy)]
+pub struct BundledAsset {
+    id: usize,
+    name: &'static str,
+    offset: usize,
+    compressed_len: usize,
+    len: usize,
+}
+
+include!(concat!(env!("OUT_DIR"), "/bundle_index.rs"));
+
+static BUNDLE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/assets.bundle"));
+
+// Decompressed on first use and kept for the lifetime of the game, None if that failed
+static DECOMPRESSED: [OnceLock<Option<Box<[u8]>>>; BUNDLED_ASSET_COUNT] =
+    [const { OnceLock::new() }; BUNDLED_ASSET_COUNT];
+
+// Material cache keys, the bundle never changes so each is hashed once
+static HASHES: [OnceLock<u64>; BUNDLED_ASSET_COUNT] =
+    [const { OnceLock::new() }; BUNDLED_ASSET_COUNT];
+
+impl BundledAsset {
+    /// The asset's bytes, decompressing it the first time it's asked for
+    pub fn data(self) -> Option<&'static [u8]> {
+        let data = DECOMPRESSED[self.id].get_or_init(|| {
+            let compressed = &BUNDLE[self.offset..self.offset + self.compressed_len];
+            match miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, self.len) {
+                Ok(data) if data.len() == self.len => {
+                    log::info!("Decompressed bundled {} ({} bytes)", self.name, self.len);
+                    Some(data.into_boxed_slice())
+                }
+                Ok(data) => {
+                    log::error!("Bundled {} is {} bytes, expected {}", self.name, data.len(), self.len);
+                    None
+                }
+                Err(e) => {
+                    log::error!("Failed to decompress bundled {}: {e:?}", self.name);
+                    None
+                }
+            }
+        });
+        data.as_deref()
+    }
+
+    /// The asset's material cache hash
+    pub fn hash(self) -> Option<u64> {
+        let data = self.data()?;
+        Some(*HASHES[self.id].get_or_init(|| material_cache::hash_material(data)))
+    }
+}
@@ -1,90 +1,660 @@
 use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
     fs::{self, File},
-    io::{Read, Write},
-    path::Path,
-    sync::OnceLock,
+    io::{self, Read, Write},
+    os::{fd::FromRawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
 };
+use crate::overrides::{self, OVERRIDES_DIR_NAME};
+use crate::rules::{recompile_rules, reload_rules, Feature, RULES_FILE_NAME};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 // Config structure
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct ModConfig {
-    #[serde(rename = "Nohurtcam")]
-    pub no_hurt_cam: bool,
+    pub config_version: u32,
+
+    // Name of the entry in `profiles` layered over the sections below
+    pub active_profile: Option<String>,
+
+    // Named partial configs, a profile can build on another one through "inherits"
+    pub profiles: Map<String, Value>,
+
+    pub no_hurt_cam: NoHurtCamConfig,
     
-    #[serde(rename = "Nofog")]
-    pub no_fog: bool,
+    pub no_fog: NoFogConfig,
     
-    #[serde(rename = "particles_disabler")]
-    pub particles_disabler: bool,
+    pub particles_disabler: ParticlesDisablerConfig,
     
-    #[serde(rename = "java_clouds")]
-    pub java_clouds: bool,
+    pub java_clouds: FeatureToggle,
     
-    #[serde(rename = "java_cubemap")]
-    pub java_cubemap: bool,
+    pub java_cubemap: FeatureToggle,
     
-    #[serde(rename = "classic_skins")]
-    pub classic_skins: bool,
+    pub classic_skins: FeatureToggle,
     
-    #[serde(rename = "cape_physics")]
-    pub cape_physics: bool,
+    pub cape_physics: FeatureToggle,
     
-    #[serde(rename = "night_vision")]
-    pub night_vision: bool,
-    // You can add more fields as needed
-    // #[serde(rename = "CustomField")]
-    // pub custom_field: bool,
+    pub night_vision: FeatureToggle,
+
+    pub splashes: SplashesConfig,
+
+    pub loading_messages: LoadingMessagesConfig,
+
+    pub material_cache: MaterialCacheConfig,
+
+    pub trace: TraceConfig,
+
+    // Writes the APK and served bytes of every replaced asset under dump/ in the config dir
+    pub dump: FeatureToggle,
+    // You can add more fields as needed, missing keys fall back to Default
+    // pub custom_field: FeatureToggle,
+
+    // Keys this build doesn't know about, kept so rewriting the file doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 impl Default for ModConfig {
     fn default() -> Self {
         Self {
-            no_hurt_cam: true,
-            no_fog: false,
-            particles_disabler: false,
-            java_clouds: false,
-            java_cubemap: false,
-            classic_skins: false,
-            cape_physics: false,
-            night_vision: false
-            // custom_field: false,
+            config_version: CONFIG_VERSION,
+            active_profile: None,
+            profiles: Map::new(),
+            no_hurt_cam: NoHurtCamConfig::default(),
+            no_fog: NoFogConfig::default(),
+            particles_disabler: ParticlesDisablerConfig::default(),
+            java_clouds: FeatureToggle::default(),
+            java_cubemap: FeatureToggle::default(),
+            classic_skins: FeatureToggle::default(),
+            cape_physics: FeatureToggle::default(),
+            night_vision: FeatureToggle::default(),
+            splashes: SplashesConfig::default(),
+            loading_messages: LoadingMessagesConfig::default(),
+            material_cache: MaterialCacheConfig::default(),
+            trace: TraceConfig::default(),
+            dump: FeatureToggle::default(),
+            // custom_field: FeatureToggle::default(),
+            extra: Map::new(),
+        }
+    }
+}
+
+// Section for features that have nothing to tune besides being on or off
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct FeatureToggle {
+    pub enabled: bool,
+}
+
+// Where no_fog takes the fog out
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NoFogMode {
+    // Fogless built-in materials, they only fit renderers close to the one they were built for
+    #[default]
+    Materials,
+    // Pushes the distances in fogs/*.json and biomes_client.json out, works on any version
+    FogDefinitions,
+}
+
+// In materials mode each category swaps the materials it covers for fogless ones
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NoFogConfig {
+    pub enabled: bool,
+    pub mode: NoFogMode,
+    // RenderChunk
+    pub terrain: bool,
+    // ActorBanner, ActorGlint
+    pub actors: bool,
+    // ItemInHandColor, ItemInHandColorGlint, ItemInHandTextured
+    pub held_items: bool,
+    // Particle
+    pub particles: bool,
+    // LegacyCubemap
+    pub sky: bool,
+    // Where fog starts in fog_definitions mode, as a multiple of the render distance
+    pub fog_distance: f64,
+    // Fog colour in fog_definitions mode as "#RRGGBB", unset keeps each fog's own
+    pub fog_color: Option<String>,
+}
+
+impl NoFogConfig {
+    pub fn uses(&self, mode: NoFogMode) -> bool {
+        self.enabled && self.mode == mode
+    }
+}
+
+impl Default for NoFogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: NoFogMode::Materials,
+            terrain: true,
+            actors: true,
+            held_items: true,
+            particles: true,
+            sky: true,
+            fog_distance: 3.0,
+            fog_color: None,
+        }
+    }
+}
+
+// Thins out particle effects from particles/*.json instead of blocking them, so hits
+// and block breaking still give feedback. Effects are picked by identifier, e.g.
+// "minecraft:critical_hit_emitter", a trailing * matches any suffix and a missing
+// namespace means minecraft:
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ParticlesDisablerConfig {
+    pub enabled: bool,
+    // Multiplies emitter rates, particle counts and lifetimes, 0 leaves nothing
+    pub density: f64,
+    // Never touched, wins over drop
+    pub keep: Vec<String>,
+    // Effects to thin out, empty means everything not in keep
+    pub drop: Vec<String>,
+}
+
+impl Default for ParticlesDisablerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 0.25,
+            keep: Vec::new(),
+            drop: Vec::new(),
         }
     }
 }
 
-// Global config instance
-static CONFIG: OnceLock<ModConfig> = OnceLock::new();
+// How our messages combine with the vanilla ones
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageMode {
+    #[default]
+    Replace,
+    Append,
+    // Serve an empty list
+    Disable,
+}
 
-// Config file path
-const CONFIG_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods";
-const CONFIG_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/config.json";
+// Messages come from splashes.txt in the config dir, or the built-in ones.
+// Off by default so vanilla splashes are untouched until the user opts in
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SplashesConfig {
+    pub enabled: bool,
+    pub mode: MessageMode,
+}
+
+impl Default for SplashesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: MessageMode::Replace,
+        }
+    }
+}
+
+// Messages come from loading_messages.json in the config dir, or the built-in ones.
+// Off by default like splashes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoadingMessagesConfig {
+    pub enabled: bool,
+    pub mode: MessageMode,
+    // Per *_loading_messages array, overriding mode
+    pub categories: BTreeMap<String, MessageMode>,
+}
+
+impl Default for LoadingMessagesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: MessageMode::Replace,
+            categories: BTreeMap::new(),
+        }
+    }
+}
+
+// Keeps materials converted to the game's version under cache/materials in the config dir
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MaterialCacheConfig {
+    pub enabled: bool,
+    // Least recently used entries are dropped past this
+    pub max_size_mib: u64,
+}
+
+impl Default for MaterialCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_size_mib: 64,
+        }
+    }
+}
+
+// Logs every AAssetManager_open to asset_trace.log in the config dir, for pack authors
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TraceConfig {
+    pub enabled: bool,
+    // The log is rotated once it would grow past this
+    pub max_size_kib: u64,
+    // Rotated logs kept next to the current one, as asset_trace.log.1 and so on
+    pub max_files: u32,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size_kib: 1024,
+            max_files: 3,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NoHurtCamConfig {
+    pub enabled: bool,
+    pub fov: f64,
+    pub third_person_radius: f64,
+    pub near_clipping_plane: f64,
+    pub far_clipping_plane: f64,
+}
+
+impl Default for NoHurtCamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fov: 66.0,
+            third_person_radius: 4.0,
+            near_clipping_plane: 0.025,
+            far_clipping_plane: 2500.0,
+        }
+    }
+}
+
+// Schema version written by this build, bump it and append a migration when keys change
+pub const CONFIG_VERSION: u32 = 2;
+
+type Migration = fn(&mut Map<String, Value>);
+
+// MIGRATIONS[n] upgrades a version n config to version n + 1
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [
+    migrate_v0_snake_case_keys,
+    migrate_v1_feature_sections,
+];
+
+// Version 0 files have no config_version and two CamelCase keys
+fn migrate_v0_snake_case_keys(config: &mut Map<String, Value>) {
+    for (old_key, new_key) in [("Nohurtcam", "no_hurt_cam"), ("Nofog", "no_fog")] {
+        if let Some(value) = config.remove(old_key) {
+            config.entry(new_key).or_insert(value);
+        }
+    }
+}
+
+// Version 1 files store every feature as a plain bool
+fn migrate_v1_feature_sections(config: &mut Map<String, Value>) {
+    const FEATURE_KEYS: [&str; 8] = [
+        "no_hurt_cam",
+        "no_fog",
+        "particles_disabler",
+        "java_clouds",
+        "java_cubemap",
+        "classic_skins",
+        "cape_physics",
+        "night_vision",
+    ];
+    for key in FEATURE_KEYS {
+        if let Some(value) = config.get_mut(key) {
+            if let Value::Bool(enabled) = *value {
+                *value = serde_json::json!({ "enabled": enabled });
+            }
+        }
+    }
+}
+
+/// Runs every pending migration in order on the config and its profiles,
+/// returns whether the config changed
+fn migrate_config(config: &mut Map<String, Value>) -> bool {
+    let version = config
+        .get("config_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    if version >= CONFIG_VERSION as u64 {
+        if version > CONFIG_VERSION as u64 {
+            log::warn!("Config version {version} is newer than {CONFIG_VERSION}, unknown keys are kept as is");
+        }
+        return false;
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!("Migrating config from version {} to {}", from, from + 1);
+        migration(config);
+        if let Some(Value::Object(profiles)) = config.get_mut("profiles") {
+            for profile in profiles.values_mut().filter_map(Value::as_object_mut) {
+                migration(profile);
+            }
+        }
+    }
+    config.insert("config_version".into(), CONFIG_VERSION.into());
+    true
+}
+
+// Keys a profile can't override, they only make sense at the top level
+const PROFILE_RESERVED_KEYS: [&str; 3] = ["config_version", "active_profile", "profiles"];
+const PROFILE_INHERITS_KEY: &str = "inherits";
+
+/// Layers the active profile and everything it inherits over the top level sections
+fn resolve_active_profile(raw: &Map<String, Value>) -> Map<String, Value> {
+    let mut resolved = raw.clone();
+    let Some(active) = raw.get("active_profile").and_then(Value::as_str) else {
+        return resolved;
+    };
+    let empty = Map::new();
+    let profiles = raw
+        .get("profiles")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    // Walk up the inherits chain, the active profile ends up last
+    let mut chain: Vec<(&str, &Map<String, Value>)> = Vec::new();
+    let mut next = Some(active);
+    while let Some(name) = next {
+        if chain.iter().any(|(seen, _)| *seen == name) {
+            log::warn!("Profile {name} inherits from itself, ignoring the rest of the chain");
+            break;
+        }
+        let Some(profile) = profiles.get(name).and_then(Value::as_object) else {
+            log::warn!("Profile {name} does not exist, ignoring it");
+            break;
+        };
+        chain.push((name, profile));
+        next = profile.get(PROFILE_INHERITS_KEY).and_then(Value::as_str);
+    }
+
+    for (_, profile) in chain.iter().rev() {
+        for (key, value) in profile.iter() {
+            if key == PROFILE_INHERITS_KEY || PROFILE_RESERVED_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            merge_value(resolved.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+    let chain_names: Vec<&str> = chain.iter().rev().map(|(name, _)| *name).collect();
+    log::info!("Using config profile {} ({})", active, chain_names.join(" -> "));
+    resolved
+}
+
+// Objects are merged key by key so a profile only has to list what it changes
+fn merge_value(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_value(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+// Global config instance, swapped as a whole when config.json changes
+static CONFIG: Lazy<RwLock<Arc<ModConfig>>> =
+    Lazy::new(|| RwLock::new(Arc::new(ModConfig::default())));
+
+// Config location, resolved once at startup by resolve_config_dir
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+const CONFIG_FILE_NAME: &str = "config.json";
+const CONFIG_ERRORS_FILE_NAME: &str = "config_errors.txt";
+// Overrides every other config location when set
+const CONFIG_DIR_ENV: &str = "ORIGIN_MODS_DIR";
+const CONFIG_DIR_NAME: &str = "origin_mods";
+const DEFAULT_PACKAGE: &str = "com.origin.launcher";
+
+pub fn config_dir() -> &'static Path {
+    CONFIG_DIR.get_or_init(resolve_config_dir)
+}
+
+pub fn config_file() -> PathBuf {
+    config_dir().join(CONFIG_FILE_NAME)
+}
+
+// Fallback order: $ORIGIN_MODS_DIR, the host app's external files dir,
+// the host app's internal files dir, then the Origin launcher's external files dir
+fn resolve_config_dir() -> PathBuf {
+    let mut candidates = Vec::new();
+    if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        candidates.push(("environment", PathBuf::from(dir)));
+    }
+    match current_package_name() {
+        Some(package) => {
+            candidates.push(("external files dir", external_files_dir(&package)));
+            candidates.push(("internal files dir", internal_files_dir(&package)));
+        }
+        None => log::warn!("Could not read the package name from /proc/self/cmdline"),
+    }
+    let default_dir = external_files_dir(DEFAULT_PACKAGE);
+    candidates.push(("default", default_dir.clone()));
+
+    for (source, dir) in candidates {
+        match fs::create_dir_all(&dir) {
+            Ok(()) => {
+                log::info!("Using config dir {} ({})", dir.display(), source);
+                return dir;
+            }
+            Err(e) => log::warn!("Config dir {} ({}) is not usable: {}", dir.display(), source, e),
+        }
+    }
+    log::warn!("No usable config dir, falling back to {}", default_dir.display());
+    default_dir
+}
+
+fn current_package_name() -> Option<String> {
+    let cmdline = fs::read("/proc/self/cmdline").ok()?;
+    // argv[0] is the package name, secondary processes add a ":name" suffix
+    let argv0 = cmdline.split(|&b| b == 0).next()?;
+    let argv0 = std::str::from_utf8(argv0).ok()?;
+    let package = argv0.split(':').next()?;
+    if package.is_empty() || package.contains('/') {
+        return None;
+    }
+    Some(package.to_owned())
+}
+
+fn external_files_dir(package: &str) -> PathBuf {
+    Path::new("/storage/emulated/0/Android/data")
+        .join(package)
+        .join("files")
+        .join(CONFIG_DIR_NAME)
+}
+
+fn internal_files_dir(package: &str) -> PathBuf {
+    Path::new("/data/data")
+        .join(package)
+        .join("files")
+        .join(CONFIG_DIR_NAME)
+}
 
 pub fn init_config() {
     let config = load_or_create_config();
-    CONFIG.set(config).expect("Failed to set config");
+    set_config(config);
+    start_config_watcher();
+}
+
+/// Returns a snapshot of the live config, later reloads do not affect it
+pub fn get_config() -> Arc<ModConfig> {
+    CONFIG.read().unwrap().clone()
+}
+
+fn set_config(config: ModConfig) {
+    *CONFIG.write().unwrap() = Arc::new(config);
+}
+
+/// Re-parses config.json and swaps it in, keeping the old config if parsing fails
+pub fn reload_config() {
+    let new_config = match load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Failed to reload config, keeping the previous one: {}", e);
+            // The user is probably mid-edit, so leave the file alone and only explain
+            write_config_errors(e.as_ref(), None);
+            return;
+        }
+    };
+    clear_config_errors();
+    let old_config = get_config();
+    log_changed_toggles(&old_config, &new_config);
+    set_config(new_config);
+    log::info!("Reloaded config from {}", config_file().display());
+    // Toggled features have to be added to or dropped from the matcher
+    recompile_rules();
+    // The watcher reindexes overrides on its own, this covers trees it couldn't watch
+    overrides::reindex_overrides();
 }
 
-pub fn get_config() -> &'static ModConfig {
-    CONFIG.get().expect("Config not initialized")
+// The game caches most assets after loading them, so a swapped config only
+// reaches the assets it opens afterwards
+fn toggle_states(config: &ModConfig) -> [(&'static str, bool, &'static str); 10] {
+    [
+        ("no_hurt_cam", config.no_hurt_cam.enabled, "rejoin the world"),
+        ("no_fog", config.no_fog.enabled, "reload resource packs"),
+        ("particles_disabler", config.particles_disabler.enabled, "reload resource packs"),
+        ("java_clouds", config.java_clouds.enabled, "reload resource packs"),
+        ("java_cubemap", config.java_cubemap.enabled, "reload resource packs"),
+        ("classic_skins", config.classic_skins.enabled, "restart the game"),
+        ("cape_physics", config.cape_physics.enabled, "reload resource packs"),
+        ("night_vision", config.night_vision.enabled, "reload resource packs"),
+        ("splashes", config.splashes.enabled, "restart the game"),
+        ("loading_messages", config.loading_messages.enabled, "restart the game"),
+    ]
+}
+
+fn log_changed_toggles(old: &ModConfig, new: &ModConfig) {
+    let changed = toggle_states(old)
+        .into_iter()
+        .zip(toggle_states(new))
+        .filter(|(old, new)| old.1 != new.1);
+    for ((name, old_value, _), (_, new_value, apply_hint)) in changed {
+        log::info!("{name} changed {old_value} -> {new_value}, {apply_hint} for it to apply");
+    }
+}
+
+fn start_config_watcher() {
+    let spawned = std::thread::Builder::new()
+        .name("origin-config-watcher".into())
+        .spawn(|| {
+            if let Err(e) = watch_config_dir() {
+                log::error!("Config watcher stopped, hot reload is disabled: {}", e);
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to spawn config watcher: {}", e);
+    }
+}
+
+fn watch_config_dir() -> io::Result<()> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Owning the fd through a File closes it for us on every return path
+    let mut inotify = unsafe { File::from_raw_fd(fd) };
+    let dir = CString::new(config_dir().as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // Editors either write in place or rename a temp file over the config.
+    // IN_CREATE is only there to notice the overrides dir being made
+    let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+    let config_watch = unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) };
+    if config_watch < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    overrides::watch_overrides(fd);
+    log::info!("Watching {} for config changes", config_dir().display());
+
+    const EVENT_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut buf = [0u8; 4096];
+    loop {
+        let read = match inotify.read(&mut buf) {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let mut config_changed = false;
+        let mut rules_changed = false;
+        let mut overrides_changed = false;
+        let mut offset = 0;
+        while offset + EVENT_SIZE <= read {
+            let event: libc::inotify_event =
+                unsafe { buf.as_ptr().add(offset).cast::<libc::inotify_event>().read_unaligned() };
+            let name_start = offset + EVENT_SIZE;
+            let name_end = (name_start + event.len as usize).min(read);
+            let name = &buf[name_start..name_end];
+            // The name is nul padded to the event length
+            let name = CStr::from_bytes_until_nul(name).map_or(name, CStr::to_bytes);
+            if event.wd != config_watch || name == OVERRIDES_DIR_NAME.as_bytes() {
+                // Anything under overrides/, or the dir itself showing up
+                overrides_changed = true;
+            } else if event.mask & libc::IN_CREATE != 0 {
+                // Files are read once whoever created them closes them
+            } else if name == CONFIG_FILE_NAME.as_bytes() {
+                config_changed = true;
+            } else if name == RULES_FILE_NAME.as_bytes() {
+                rules_changed = true;
+            }
+            offset = name_end;
+        }
+        if config_changed {
+            reload_config();
+        }
+        if rules_changed {
+            reload_rules();
+        }
+        if overrides_changed {
+            overrides::reindex_overrides();
+            // New dirs need watches of their own
+            overrides::watch_overrides(fd);
+        }
+    }
 }
 
 fn load_or_create_config() -> ModConfig {
     // Create directory if it doesn't exist
-    if let Err(e) = fs::create_dir_all(CONFIG_DIR) {
+    if let Err(e) = fs::create_dir_all(config_dir()) {
         log::warn!("Failed to create config directory: {}", e);
         return ModConfig::default();
     }
 
     // Try to load existing config
-    if Path::new(CONFIG_FILE).exists() {
+    if config_file().exists() {
         match load_config() {
             Ok(config) => {
-                log::info!("Loaded config from {}", CONFIG_FILE);
+                log::info!("Loaded config from {}", config_file().display());
+                clear_config_errors();
                 return config;
             }
+            Err(e) if e.is::<io::Error>() => {
+                // The file may be fine, we just can't read it, so don't replace it
+                log::warn!("Failed to read config, using default: {}", e);
+                return ModConfig::default();
+            }
             Err(e) => {
-                log::warn!("Failed to load config, using default: {}", e);
+                log::warn!("Failed to parse config, using default: {}", e);
+                let backup = backup_broken_config();
+                write_config_errors(e.as_ref(), backup.as_deref());
+                if backup.is_none() {
+                    return ModConfig::default();
+                }
             }
         }
     }
@@ -94,62 +664,150 @@ fn load_or_create_config() -> ModConfig {
     if let Err(e) = save_config(&default_config) {
         log::warn!("Failed to save default config: {}", e);
     } else {
-        log::info!("Created default config at {}", CONFIG_FILE);
+        log::info!("Created default config at {}", config_file().display());
     }
 
     default_config
 }
 
 fn load_config() -> Result<ModConfig, Box<dyn std::error::Error>> {
-    let mut file = File::open(CONFIG_FILE)?;
+    let mut file = File::open(config_file())?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
     
-    let config: ModConfig = serde_json::from_str(&contents)?;
+    let mut raw: Value = serde_json::from_str(&contents)?;
+    let Some(raw_map) = raw.as_object_mut() else {
+        return Err("config.json must contain a JSON object".into());
+    };
+    let migrated = migrate_config(raw_map);
+    let resolved = resolve_active_profile(raw_map);
+    let config: ModConfig = match serde_json::from_value(Value::Object(resolved)) {
+        Ok(config) => config,
+        // from_value errors have no position, parsing the file's text again finds one
+        // unless the bad field came from a profile or a migration
+        Err(e) if !migrated => match serde_json::from_str::<ModConfig>(&contents) {
+            Err(positioned) if positioned.line() != 0 => return Err(positioned.into()),
+            _ => return Err(e.into()),
+        },
+        Err(e) => return Err(e.into()),
+    };
+    if migrated {
+        // Save the migrated file itself, the resolved config has the profile baked in
+        match save_config(&raw) {
+            Ok(()) => log::info!("Saved migrated config to {}", config_file().display()),
+            Err(e) => log::warn!("Failed to save migrated config: {}", e),
+        }
+    }
     Ok(config)
 }
 
-fn save_config(config: &ModConfig) -> Result<(), Box<dyn std::error::Error>> {
+// Written next to config.json and renamed over it, so a crash never leaves a truncated config
+fn save_config<T: Serialize>(config: &T) -> Result<(), Box<dyn std::error::Error>> {
     let json = serde_json::to_string_pretty(config)?;
-    let mut file = File::create(CONFIG_FILE)?;
+    let tmp_path = config_dir().join(format!("{CONFIG_FILE_NAME}.tmp"));
+    let mut file = File::create(&tmp_path)?;
     file.write_all(json.as_bytes())?;
     file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, config_file())?;
+    // Persist the rename itself, failing here only risks losing this save
+    if let Ok(dir) = File::open(config_dir()) {
+        let _ = dir.sync_all();
+    }
     Ok(())
 }
 
-// Helper functions to check individual settings
+/// Moves an unparseable config.json out of the way, returns where it went
+fn backup_broken_config() -> Option<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let backup = config_dir().join(format!("{CONFIG_FILE_NAME}.bad-{timestamp}"));
+    match fs::rename(config_file(), &backup) {
+        Ok(()) => {
+            log::warn!("Moved broken config to {}", backup.display());
+            Some(backup)
+        }
+        Err(e) => {
+            log::error!("Failed to back up broken config, leaving it in place: {}", e);
+            None
+        }
+    }
+}
+
+fn config_errors_file() -> PathBuf {
+    config_dir().join(CONFIG_ERRORS_FILE_NAME)
+}
+
+fn write_config_errors(error: &(dyn std::error::Error + 'static), backup: Option<&Path>) {
+    let mut report = format!("{} could not be loaded:\n{}\n", config_file().display(), error);
+    if let Some(json_error) = error.downcast_ref::<serde_json::Error>() {
+        // Errors from profile or migrated fields have no position
+        if json_error.line() != 0 {
+            report += &format!("at line {}, column {}\n", json_error.line(), json_error.column());
+        }
+    }
+    match backup {
+        Some(backup) => {
+            report += &format!("\nThe file was moved to {} and replaced with defaults.\n", backup.display());
+        }
+        None => report += "\nThe previous settings stay active until the file is fixed.\n",
+    }
+    if let Err(e) = fs::write(config_errors_file(), report) {
+        log::warn!("Failed to write {}: {}", CONFIG_ERRORS_FILE_NAME, e);
+    }
+}
+
+fn clear_config_errors() {
+    match fs::remove_file(config_errors_file()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to remove {}: {}", CONFIG_ERRORS_FILE_NAME, e),
+    }
+}
+
+// Helper functions to check individual settings. The asset rules go through Feature
+// themselves, these stay for code that isn't a rule
+#[allow(dead_code)]
 pub fn is_no_hurt_cam_enabled() -> bool {
-    get_config().no_hurt_cam
+    Feature::NoHurtCam.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_no_fog_enabled() -> bool {
-    get_config().no_fog
+    Feature::NoFog.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_particles_disabler_enabled() -> bool {
-    get_config().particles_disabler
+    Feature::ParticlesDisabler.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_java_clouds_enabled() -> bool {
-    get_config().java_clouds
+    Feature::JavaClouds.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_java_cubemap_enabled() -> bool {
-    get_config().java_cubemap
+    Feature::JavaCubemap.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_classic_skins_enabled() -> bool {
-    get_config().classic_skins
+    Feature::ClassicSkins.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_cape_physics_enabled() -> bool {
-    get_config().cape_physics
+    Feature::CapePhysics.is_enabled(&get_config())
 }
 
+#[allow(dead_code)]
 pub fn is_night_vision_enabled() -> bool {
-    get_config().night_vision
+    Feature::NightVision.is_enabled(&get_config())
 }
 // You can add more helper functions for other config values
 // pub fn is_custom_field_enabled() -> bool {
-//     get_config().custom_field
-// }
\ No newline at end of file
+//     get_config().custom_field.enabled
+// }
@@ -0,0 +1,45 @@
+use crate::config::config_dir;
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+// dump/original/<apk path> and dump/served/<apk path>
+pub const DUMP_DIR_NAME: &str = "dump";
+
+/// Writes what the APK has for an asset and what the game was handed instead,
+/// original is None when the APK doesn't have the asset
+pub fn dump_asset(path: &Path, original: Option<&[u8]>, served: &[u8]) {
+    let Some(relative) = sanitize(path) else {
+        log::warn!("Not dumping {}, it has no usable components", path.display());
+        return;
+    };
+    let root = config_dir().join(DUMP_DIR_NAME);
+    if let Some(original) = original {
+        write_dump(&root.join("original").join(&relative), original);
+    }
+    write_dump(&root.join("served").join(&relative), served);
+}
+
+// Keeps the dump inside its dir whatever path the game passed in
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let relative: PathBuf = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+fn write_dump(file: &Path, data: &[u8]) {
+    let result = match file.parent() {
+        Some(parent) => fs::create_dir_all(parent).and_then(|()| fs::write(file, data)),
+        None => Err(io::ErrorKind::NotFound.into()),
+    };
+    match result {
+        Ok(()) => log::debug!("Dumped {}", file.display()),
+        Err(e) => log::warn!("Failed to dump {}: {e}", file.display()),
+    }
+}
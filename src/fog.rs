@@ -0,0 +1,83 @@
+use crate::aasset::AssetData;
+use crate::config::NoFogConfig;
+use crate::json_patch::parse_lenient;
+use crate::rules::TransformContext;
+use serde_json::{json, Map, Value};
+
+// How far past fog_start the fog takes to thicken, as a share of fog_start.
+// Keeps start and end apart, the game divides by their difference
+const FOG_FADE: f64 = 0.1;
+
+/// Serves fogs/*.json and biomes_client.json with every fog pushed out to fog_distance.
+/// No shaders change, so this works whatever renderer the game ships
+pub fn fog_definitions_transform(ctx: &TransformContext) -> Option<AssetData> {
+    let config = &ctx.config.no_fog;
+    if ctx.path.extension().is_none_or(|extension| extension != "json") {
+        return None;
+    }
+    let distance = config.fog_distance;
+    if !distance.is_finite() || distance <= 0.0 {
+        log::warn!("Not moving fog in {}, fog_distance {distance} isn't a positive number", ctx.path.display());
+        return None;
+    }
+    let original = ctx.read_original()?;
+    let mut definitions = match parse_lenient(&original) {
+        Ok(definitions) => definitions,
+        Err(e) => {
+            log::warn!("Not moving fog in {}, it is not valid JSON: {e}", ctx.path.display());
+            return None;
+        }
+    };
+    let color = fog_color(config);
+    if push_out_fogs(&mut definitions, distance, color.as_ref()) == 0 {
+        return None;
+    }
+    Some(definitions.to_string().into_bytes().into())
+}
+
+fn fog_color(config: &NoFogConfig) -> Option<Value> {
+    let color = config.fog_color.as_deref()?;
+    let is_hex = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex {
+        log::warn!("Ignoring fog_color {color:?}, expected \"#RRGGBB\"");
+        return None;
+    }
+    Some(Value::from(color))
+}
+
+// Any object with fog_start and fog_end is a fog distance, which covers every medium
+// in fogs/*.json, water's transition_fog and fogs written inline in biomes.
+// Returns how many were changed
+fn push_out_fogs(value: &mut Value, distance: f64, color: Option<&Value>) -> usize {
+    match value {
+        Value::Object(object) => {
+            let mut changed = 0;
+            if object.contains_key("fog_start") && object.contains_key("fog_end") {
+                push_out(object, distance, color);
+                changed += 1;
+            }
+            for child in object.values_mut() {
+                changed += push_out_fogs(child, distance, color);
+            }
+            changed
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .map(|item| push_out_fogs(item, distance, color))
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn push_out(fog: &mut Map<String, Value>, distance: f64, color: Option<&Value>) {
+    // Fixed fogs are in blocks, but how far the player can see isn't known here,
+    // so every fog becomes relative to the render distance
+    fog.insert("render_distance_type".into(), "render".into());
+    fog.insert("fog_start".into(), json!(distance));
+    fog.insert("fog_end".into(), json!(distance * (1.0 + FOG_FADE)));
+    if let Some(color) = color {
+        fog.insert("fog_color".into(), color.clone());
+    }
+}
@@ -0,0 +1,341 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// A patch applied to a vanilla JSON asset instead of replacing it outright
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonPatch {
+    // RFC 7386
+    MergePatch(Value),
+    // RFC 6902
+    JsonPatch(Vec<Operation>),
+}
+
+impl JsonPatch {
+    pub fn apply(&self, target: &mut Value) -> Result<(), String> {
+        match self {
+            Self::MergePatch(patch) => {
+                merge_patch(target, patch);
+                Ok(())
+            }
+            Self::JsonPatch(operations) => apply_operations(target, operations),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// RFC 7386: objects merge recursively, null removes a key, anything else replaces
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("target was just made an object");
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// RFC 6902, either every operation applies or the target is left untouched
+pub fn apply_operations(target: &mut Value, operations: &[Operation]) -> Result<(), String> {
+    let mut patched = target.clone();
+    for operation in operations {
+        apply_operation(&mut patched, operation)?;
+    }
+    *target = patched;
+    Ok(())
+}
+
+fn apply_operation(doc: &mut Value, operation: &Operation) -> Result<(), String> {
+    match operation {
+        Operation::Add { path, value } => add(doc, path, value.clone()),
+        Operation::Remove { path } => remove(doc, path).map(drop),
+        Operation::Replace { path, value } => {
+            let target = doc
+                .pointer_mut(path)
+                .ok_or_else(|| format!("replace: {path:?} does not exist"))?;
+            *target = value.clone();
+            Ok(())
+        }
+        Operation::Move { from, path } => {
+            if from == path {
+                return Ok(());
+            }
+            if path.starts_with(&format!("{from}/")) {
+                return Err(format!("move: {from:?} can't be moved into itself"));
+            }
+            let value = remove(doc, from)?;
+            add(doc, path, value)
+        }
+        Operation::Copy { from, path } => {
+            let value = doc
+                .pointer(from)
+                .cloned()
+                .ok_or_else(|| format!("copy: {from:?} does not exist"))?;
+            add(doc, path, value)
+        }
+        Operation::Test { path, value } => match doc.pointer(path) {
+            Some(actual) if actual == value => Ok(()),
+            _ => Err(format!("test: {path:?} does not match")),
+        },
+    }
+}
+
+// Splits a JSON pointer into its parent pointer and the unescaped last token
+fn split_pointer(path: &str) -> Result<(&str, String), String> {
+    let split = path
+        .rfind('/')
+        .ok_or_else(|| format!("{path:?} is not a JSON pointer to a child value"))?;
+    let token = path[split + 1..].replace("~1", "/").replace("~0", "~");
+    Ok((&path[..split], token))
+}
+
+fn array_index(token: &str, bound: usize) -> Result<usize, String> {
+    // Leading zeros are not allowed by RFC 6901
+    if token.len() > 1 && token.starts_with('0') {
+        return Err(format!("{token:?} is not a valid array index"));
+    }
+    match token.parse::<usize>() {
+        Ok(index) if index < bound => Ok(index),
+        _ => Err(format!("{token:?} is not a valid array index")),
+    }
+}
+
+fn add(doc: &mut Value, path: &str, value: Value) -> Result<(), String> {
+    if path.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (parent, token) = split_pointer(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.insert(token, value);
+        }
+        Some(Value::Array(array)) => {
+            let index = match token.as_str() {
+                "-" => array.len(),
+                // Inserting right after the last element is allowed
+                token => array_index(token, array.len() + 1)?,
+            };
+            array.insert(index, value);
+        }
+        _ => return Err(format!("add: {parent:?} is not an object or array")),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, path: &str) -> Result<Value, String> {
+    let (parent, token) = split_pointer(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(map)) => map
+            .remove(&token)
+            .ok_or_else(|| format!("remove: {path:?} does not exist")),
+        Some(Value::Array(array)) => {
+            let index = array_index(&token, array.len())?;
+            Ok(array.remove(index))
+        }
+        _ => Err(format!("remove: {path:?} does not exist")),
+    }
+}
+
+/// Parses a vanilla JSON asset, which may contain comments serde_json rejects
+pub fn parse_lenient(data: &[u8]) -> Result<Value, serde_json::Error> {
+    match serde_json::from_slice(data) {
+        Ok(value) => Ok(value),
+        Err(e) => match strip_comments(data) {
+            Some(stripped) => serde_json::from_slice(&stripped),
+            None => Err(e),
+        },
+    }
+}
+
+// Removes // and /* */ comments outside of strings, None if there were none
+fn strip_comments(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut found = false;
+    let mut in_string = false;
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        if in_string {
+            output.push(byte);
+            match byte {
+                b'\\' if i + 1 < data.len() => {
+                    output.push(data[i + 1]);
+                    i += 1;
+                }
+                b'"' => in_string = false,
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+        match (byte, data.get(i + 1)) {
+            (b'"', _) => {
+                in_string = true;
+                output.push(byte);
+                i += 1;
+            }
+            (b'/', Some(b'/')) => {
+                found = true;
+                while i < data.len() && data[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            (b'/', Some(b'*')) => {
+                found = true;
+                i += 2;
+                while i < data.len() && !data[i..].starts_with(b"*/") {
+                    i += 1;
+                }
+                i += 2;
+            }
+            _ => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    found.then_some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn operations(operations: Value) -> Vec<Operation> {
+        serde_json::from_value(operations).unwrap()
+    }
+
+    fn patched(mut target: Value, ops: Value) -> Result<Value, String> {
+        apply_operations(&mut target, &operations(ops))?;
+        Ok(target)
+    }
+
+    #[test]
+    fn add_to_arrays() {
+        let target = json!({ "list": [1, 2] });
+        let appended = patched(
+            target.clone(),
+            json!([{ "op": "add", "path": "/list/-", "value": 3 }]),
+        );
+        assert_eq!(appended, Ok(json!({ "list": [1, 2, 3] })));
+        let at_end = patched(
+            target.clone(),
+            json!([{ "op": "add", "path": "/list/2", "value": 3 }]),
+        );
+        assert_eq!(at_end, Ok(json!({ "list": [1, 2, 3] })));
+        let inserted = patched(
+            target.clone(),
+            json!([{ "op": "add", "path": "/list/0", "value": 0 }]),
+        );
+        assert_eq!(inserted, Ok(json!({ "list": [0, 1, 2] })));
+        assert!(patched(
+            target.clone(),
+            json!([{ "op": "add", "path": "/list/3", "value": 3 }])
+        )
+        .is_err());
+        assert!(patched(
+            target,
+            json!([{ "op": "add", "path": "/list/01", "value": 3 }])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn escaped_tokens() {
+        let target = json!({ "a/b": 1, "c~d": 2 });
+        let ops = json!([
+            { "op": "replace", "path": "/a~1b", "value": 3 },
+            { "op": "remove", "path": "/c~0d" }
+        ]);
+        assert_eq!(patched(target, ops), Ok(json!({ "a/b": 3 })));
+    }
+
+    #[test]
+    fn move_and_copy() {
+        let target = json!({ "a": { "x": 1 }, "b": {} });
+        let moved = patched(
+            target.clone(),
+            json!([{ "op": "move", "from": "/a/x", "path": "/b/x" }]),
+        );
+        assert_eq!(moved, Ok(json!({ "a": {}, "b": { "x": 1 } })));
+        // A sibling whose name starts with the source is not inside it
+        let renamed = patched(
+            target.clone(),
+            json!([{ "op": "move", "from": "/a", "path": "/ab" }]),
+        );
+        assert_eq!(renamed, Ok(json!({ "ab": { "x": 1 }, "b": {} })));
+        let into_child = patched(
+            target.clone(),
+            json!([{ "op": "move", "from": "/a", "path": "/a/y" }]),
+        );
+        assert!(into_child.is_err());
+        let copied = patched(
+            target,
+            json!([{ "op": "copy", "from": "/a", "path": "/b/a" }]),
+        );
+        assert_eq!(
+            copied,
+            Ok(json!({ "a": { "x": 1 }, "b": { "a": { "x": 1 } } }))
+        );
+    }
+
+    #[test]
+    fn failed_patch_leaves_target_untouched() {
+        let original = json!({ "a": 1, "list": [1] });
+        let mut target = original.clone();
+        let ops = operations(json!([
+            { "op": "add", "path": "/b", "value": 2 },
+            { "op": "remove", "path": "/list/0" },
+            { "op": "test", "path": "/a", "value": 2 }
+        ]));
+        assert!(apply_operations(&mut target, &ops).is_err());
+        assert_eq!(target, original);
+        assert!(apply_operations(
+            &mut target,
+            &operations(json!([{ "op": "replace", "path": "/missing", "value": 1 }]))
+        )
+        .is_err());
+        assert_eq!(target, original);
+    }
+
+    #[test]
+    fn merge_patch_removes_nulls() {
+        let mut target = json!({ "a": 1, "b": { "c": 2, "d": 3 } });
+        merge_patch(&mut target, &json!({ "a": null, "b": { "c": 4 } }));
+        assert_eq!(target, json!({ "b": { "c": 4, "d": 3 } }));
+    }
+
+    #[test]
+    fn lenient_parsing_strips_comments_outside_strings() {
+        let data = br#"{
+            // a comment
+            "url": "https://example.com", /* another */ "n": 1
+        }"#;
+        assert_eq!(
+            parse_lenient(data).unwrap(),
+            json!({ "url": "https://example.com", "n": 1 })
+        );
+    }
+}
@@ -9,6 +9,19 @@ use std::{
 mod config;
 use config::init_config;
 mod aasset;
+mod asset_dir;
+mod bundle;
+mod dump;
+mod fog;
+mod json_patch;
+mod material_cache;
+mod material_edits;
+mod memfd;
+mod messages;
+mod overrides;
+mod particles;
+mod rules;
+mod trace;
 mod plthook;
 use crate::plthook::replace_plt_functions;
 use bhook::hook_fn;
@@ -55,10 +68,13 @@ pub fn setup_logging() {
         android_logger::Config::default().with_max_level(log::LevelFilter::Trace),
     );
 }
-#[ctor::ctor]
+// Unit tests load this library too, without a game to hook
+#[cfg_attr(not(test), ctor::ctor)]
 fn main() {
     setup_logging();
     init_config();
+    rules::init_rules();
+    overrides::init_overrides();
     log::info!("Starting");
     let mcmap = find_minecraft_library_manually()
         .expect("Cannot find libminecraftpe.so in memory maps - device not supported");
@@ -166,6 +182,10 @@ pub fn hook_aaset() {
         "AAsset_openFileDescriptor64" -> aasset::fd_dummy64,
         "AAsset_getBuffer" -> aasset::get_buffer,
         "AAsset_isAllocated" -> aasset::is_alloc,
+        "AAssetManager_openDir" -> asset_dir::open_dir,
+        "AAssetDir_getNextFileName" -> asset_dir::next_file_name,
+        "AAssetDir_rewind" -> asset_dir::rewind,
+        "AAssetDir_close" -> asset_dir::close,
     };
     replace_plt_functions(&dyn_lib, asset_fn_list);
 }
@@ -0,0 +1,205 @@
+use crate::config::{config_dir, MaterialCacheConfig};
+use materialbin::MinecraftVersion;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+// Relative to the config dir. Files are named <input hash>-<source>-<target>.bin,
+// an empty file means the input already was in the target version
+pub const MATERIAL_CACHE_DIR: &str = "cache/materials";
+// Holds the game version the cache was filled for
+const VERSION_FILE_NAME: &str = "version";
+
+struct CacheEntry {
+    file: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+struct MaterialCache {
+    dir: PathBuf,
+    entries: HashMap<u64, CacheEntry>,
+    total_size: u64,
+}
+
+// Opened on first use, once the game version is known
+static CACHE: Lazy<Mutex<Option<MaterialCache>>> = Lazy::new(|| Mutex::new(None));
+
+pub enum Cached {
+    // Already in the game's version, serve it as is
+    Unchanged,
+    Converted(Vec<u8>),
+}
+
+/// FNV-1a, only needs to tell materials apart, not resist anyone
+pub fn hash_material(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn lookup(hash: u64, target: MinecraftVersion) -> Option<Cached> {
+    // Read without holding the lock, so loader threads don't wait on each other's reads
+    let file = {
+        let mut cache = CACHE.lock().unwrap();
+        let cache = open_cache(&mut cache, target)?;
+        let entry = cache.entries.get_mut(&hash)?;
+        entry.last_used = SystemTime::now();
+        entry.file.clone()
+    };
+    match fs::read(&file) {
+        Ok(data) if data.is_empty() => Some(Cached::Unchanged),
+        Ok(data) => Some(Cached::Converted(data)),
+        Err(e) => {
+            log::warn!("Dropping unreadable cached material {}: {e}", file.display());
+            let mut cache = CACHE.lock().unwrap();
+            if let Some(cache) = cache.as_mut() {
+                // Unless it got evicted or stored again in the meantime
+                if cache.entries.get(&hash).is_some_and(|entry| entry.file == file) {
+                    let entry = cache.entries.remove(&hash).unwrap();
+                    cache.total_size -= entry.size;
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Remembers a conversion, converted is None if the input already was in the target version
+pub fn store(
+    config: &MaterialCacheConfig,
+    hash: u64,
+    source: MinecraftVersion,
+    target: MinecraftVersion,
+    converted: Option<&[u8]>,
+) {
+    let mut cache = CACHE.lock().unwrap();
+    let Some(cache) = open_cache(&mut cache, target) else {
+        return;
+    };
+    let name = format!("{hash:016x}-{}-{}.bin", version_tag(source), version_tag(target));
+    let file = cache.dir.join(name);
+    let data = converted.unwrap_or_default();
+    if let Err(e) = write_atomically(&file, data) {
+        log::warn!("Failed to cache material {}: {e}", file.display());
+        return;
+    }
+    let entry = CacheEntry {
+        file,
+        size: data.len() as u64,
+        last_used: SystemTime::now(),
+    };
+    cache.total_size += entry.size;
+    if let Some(replaced) = cache.entries.insert(hash, entry) {
+        cache.total_size -= replaced.size;
+    }
+    evict(cache, config.max_size_mib.saturating_mul(1024 * 1024));
+}
+
+// Version names end up in file names, so only keep what's safe there
+fn version_tag(version: MinecraftVersion) -> String {
+    version
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '_' })
+        .collect()
+}
+
+fn open_cache(cache: &mut Option<MaterialCache>, target: MinecraftVersion) -> Option<&mut MaterialCache> {
+    if cache.is_none() {
+        let dir = config_dir().join(MATERIAL_CACHE_DIR);
+        match load_cache(&dir, &version_tag(target)) {
+            Ok(loaded) => {
+                log::info!(
+                    "Material cache has {} entries ({} bytes) in {}",
+                    loaded.entries.len(),
+                    loaded.total_size,
+                    dir.display()
+                );
+                *cache = Some(loaded);
+            }
+            Err(e) => {
+                log::warn!("Material cache is unavailable: {e}");
+                return None;
+            }
+        }
+    }
+    cache.as_mut()
+}
+
+fn load_cache(dir: &Path, target: &str) -> io::Result<MaterialCache> {
+    let version_file = dir.join(VERSION_FILE_NAME);
+    let cached_version = fs::read_to_string(&version_file).ok();
+    if cached_version.as_deref() != Some(target) {
+        // Conversions for another game version are of no use anymore
+        if cached_version.is_some() {
+            log::info!("Game version changed, clearing the material cache");
+        }
+        match fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        fs::create_dir_all(dir)?;
+        fs::write(&version_file, target)?;
+    }
+
+    let mut entries = HashMap::new();
+    let mut total_size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(hash) = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(&format!("-{target}.bin")))
+            .and_then(|name| name.split('-').next())
+            .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+        else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        total_size += metadata.len();
+        entries.insert(
+            hash,
+            CacheEntry {
+                file: entry.path(),
+                size: metadata.len(),
+                last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            },
+        );
+    }
+    Ok(MaterialCache {
+        dir: dir.to_path_buf(),
+        entries,
+        total_size,
+    })
+}
+
+fn write_atomically(file: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = file.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, file)
+}
+
+// Drops the least recently used entries until the cache fits
+fn evict(cache: &mut MaterialCache, max_size: u64) {
+    while cache.total_size > max_size {
+        let Some(oldest) = cache
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(&hash, _)| hash)
+        else {
+            return;
+        };
+        let entry = cache.entries.remove(&oldest).unwrap();
+        cache.total_size -= entry.size;
+        if let Err(e) = fs::remove_file(&entry.file) {
+            log::warn!("Failed to evict cached material {}: {e}", entry.file.display());
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::aasset::{self, AssetData, MaterialConversion};
+use crate::bundle::{
+    BundledAsset, LEGACY_CUBEMAP_MATERIAL_BIN, NO_FOG_ACTOR_BANNER_MATERIAL_BIN,
+    NO_FOG_ACTOR_GLINT_MATERIAL_BIN, NO_FOG_ITEM_IN_HAND_COLOR_GLINT_MATERIAL_BIN,
+    NO_FOG_ITEM_IN_HAND_COLOR_MATERIAL_BIN, NO_FOG_ITEM_IN_HAND_TEXTURED_MATERIAL_BIN,
+    NO_FOG_LEGACY_CUBEMAP_MATERIAL_BIN, NO_FOG_PARTICLE_MATERIAL_BIN, RENDER_CHUNK_MATERIAL_BIN,
+    RENDER_CHUNK_NV_MATERIAL_BIN,
+};
+use crate::config::ModConfig;
+use crate::rules::{Feature, TransformContext};
+use materialbin::{CompiledMaterialDefinition, Pass, PlatformShaderStage, PropertyField, ShaderCode};
+use once_cell::sync::Lazy;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    os::unix::ffi::OsStrExt,
+    sync::{Arc, Mutex},
+};
+
+/// One feature's change to a vanilla material, built from one of the bundled materials.
+/// What it changes is worked out by diffing that material against the game's, so conflicts
+/// between features only show up once the game opens the material
+pub struct MaterialEdit {
+    pub feature: Feature,
+    // File name of the material in the APK
+    pub material: &'static str,
+    pub source: BundledAsset,
+}
+
+// Where two edits change a part differently, the earlier one keeps it
+pub const MATERIAL_EDITS: [MaterialEdit; 10] = [
+    MaterialEdit {
+        feature: Feature::NoFogTerrain,
+        material: "RenderChunk.material.bin",
+        source: RENDER_CHUNK_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NightVision,
+        material: "RenderChunk.material.bin",
+        source: RENDER_CHUNK_NV_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogActors,
+        material: "ActorBanner.material.bin",
+        source: NO_FOG_ACTOR_BANNER_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogActors,
+        material: "ActorGlint.material.bin",
+        source: NO_FOG_ACTOR_GLINT_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogHeldItems,
+        material: "ItemInHandColor.material.bin",
+        source: NO_FOG_ITEM_IN_HAND_COLOR_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogHeldItems,
+        material: "ItemInHandColorGlint.material.bin",
+        source: NO_FOG_ITEM_IN_HAND_COLOR_GLINT_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogHeldItems,
+        material: "ItemInHandTextured.material.bin",
+        source: NO_FOG_ITEM_IN_HAND_TEXTURED_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogParticles,
+        material: "Particle.material.bin",
+        source: NO_FOG_PARTICLE_MATERIAL_BIN,
+    },
+    // java_cubemap replaces the whole sky, so it goes before no_fog's cubemap
+    MaterialEdit {
+        feature: Feature::JavaCubemap,
+        material: "LegacyCubemap.material.bin",
+        source: LEGACY_CUBEMAP_MATERIAL_BIN,
+    },
+    MaterialEdit {
+        feature: Feature::NoFogSky,
+        material: "LegacyCubemap.material.bin",
+        source: NO_FOG_LEGACY_CUBEMAP_MATERIAL_BIN,
+    },
+];
+
+// A piece of the game's material an edit can change on its own. Variants are addressed by
+// their position in the game's pass, the bundled material's are matched to them by flags
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Part {
+    // A pass the game's material doesn't have
+    Pass(String),
+    // A variant is owned by one feature, so its vertex and fragment shaders always match
+    Variant { pass: String, variant: usize },
+    Uniform(String),
+}
+
+// A part of a bundled material that differs from the game's, with what to put there
+enum Change {
+    Pass(String, Pass),
+    // Only the stages that differ
+    Variant {
+        pass: String,
+        variant: usize,
+        stages: Vec<(PlatformShaderStage, ShaderCode)>,
+    },
+    Uniform(String, PropertyField),
+}
+
+impl Change {
+    fn part(&self) -> Part {
+        match self {
+            Self::Pass(name, _) => Part::Pass(name.clone()),
+            Self::Variant { pass, variant, .. } => Part::Variant {
+                pass: pass.clone(),
+                variant: *variant,
+            },
+            Self::Uniform(name, _) => Part::Uniform(name.clone()),
+        }
+    }
+
+    // Whether two edits change the same part to the same thing
+    fn same_as(&self, other: &Change) -> bool {
+        match (self, other) {
+            (Self::Pass(_, pass), Self::Pass(_, other)) => same_pass(pass, other),
+            (Self::Variant { stages, .. }, Self::Variant { stages: other, .. }) => {
+                stages.len() == other.len()
+                    && stages.iter().all(|(stage, code)| {
+                        other.iter().any(|(other_stage, other_code)| {
+                            other_stage == stage && other_code.bgfx_shader_data == code.bgfx_shader_data
+                        })
+                    })
+            }
+            (Self::Uniform(_, field), Self::Uniform(_, other)) => field == other,
+            _ => false,
+        }
+    }
+}
+
+fn same_pass(pass: &Pass, other: &Pass) -> bool {
+    pass.variants.len() == other.variants.len()
+        && pass.variants.iter().zip(&other.variants).all(|(variant, other)| {
+            variant.flags == other.flags
+                && variant.shader_codes.len() == other.shader_codes.len()
+                && variant.shader_codes.iter().zip(&other.shader_codes).all(|((stage, code), (other_stage, other_code))| {
+                    stage == other_stage && code.bgfx_shader_data == other_code.bgfx_shader_data
+                })
+        })
+}
+
+// Composed materials by file name and set of edits, None if composing failed.
+// The game's materials don't change while it runs, so each set is composed once
+type ComposedMaterials = HashMap<(&'static str, u32), Option<Arc<[u8]>>>;
+static COMPOSED: Lazy<Mutex<ComposedMaterials>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn active_edits<'a>(
+    config: &'a ModConfig,
+    material: &'a [u8],
+) -> impl Iterator<Item = (usize, &'static MaterialEdit)> + 'a {
+    MATERIAL_EDITS
+        .iter()
+        .enumerate()
+        .filter(move |(_, edit)| edit.material.as_bytes() == material && edit.feature.is_enabled(config))
+}
+
+fn warn_conflict(kept: &MaterialEdit, dropped: &MaterialEdit, shared: usize) {
+    log::warn!(
+        "{} and {} change {shared} parts of {} differently, {} keeps them",
+        kept.feature.name(),
+        dropped.feature.name(),
+        kept.material,
+        kept.feature.name(),
+    );
+}
+
+/// Serves a material with every enabled feature's edit in it, in the game's material version
+pub fn compose_transform(ctx: &TransformContext) -> Option<AssetData> {
+    let material = ctx.path.file_name()?.as_bytes();
+    let edits: Vec<(usize, &MaterialEdit)> = active_edits(ctx.config, material).collect();
+    match edits.as_slice() {
+        [] => None,
+        // Nothing to merge, so the feature's material is served whole
+        [(_, edit)] => serve_whole(ctx, edit),
+        [(_, first), ..] => {
+            let key = (first.material, edits.iter().fold(0, |set, (index, _)| set | 1 << index));
+            if let Some(composed) = COMPOSED.lock().unwrap().get(&key) {
+                return composed.clone().map(AssetData::Shared);
+            }
+            let composed: Option<Arc<[u8]>> = compose(ctx, &edits).map(Arc::from);
+            COMPOSED.lock().unwrap().insert(key, composed.clone());
+            composed.map(AssetData::Shared)
+        }
+    }
+}
+
+fn serve_whole(ctx: &TransformContext, edit: &MaterialEdit) -> Option<AssetData> {
+    let data = edit.source.data()?;
+    match aasset::process_material(ctx.manager, data, || edit.source.hash(), ctx.config) {
+        MaterialConversion::Unchanged => Some(AssetData::Static(data)),
+        MaterialConversion::Converted(converted) => Some(converted.into()),
+        MaterialConversion::Failed => {
+            refuse(ctx, "the built-in material can't be converted to the game's material version");
+            None
+        }
+    }
+}
+
+// Applies each edit's changes to the game's own material, which is already in its version
+fn compose(ctx: &TransformContext, edits: &[(usize, &MaterialEdit)]) -> Option<Vec<u8>> {
+    let Some(original) = ctx.read_original() else {
+        refuse(ctx, "the game's material can't be read");
+        return None;
+    };
+    // Parsed twice, one copy to diff against and one to change
+    let (Some((version, vanilla)), Some((_, mut composed))) =
+        (aasset::parse_material(&original), aasset::parse_material(&original))
+    else {
+        refuse(ctx, "the game's material can't be parsed");
+        return None;
+    };
+
+    let mut changes: Vec<Vec<Option<Change>>> = Vec::new();
+    for &(_, edit) in edits {
+        let Some((_, edited)) = edit.source.data().and_then(aasset::parse_material) else {
+            log::error!("Skipping {} on {}, its material can't be parsed", edit.feature.name(), ctx.path.display());
+            changes.push(Vec::new());
+            continue;
+        };
+        changes.push(diff(&vanilla, edited).into_iter().map(Some).collect());
+    }
+
+    // Each part goes to the first edit that changes it. Bundled materials made from another
+    // game version differ from the game's everywhere, but they agree with each other there,
+    // so only parts the edits change differently count as conflicts
+    let mut owners: HashMap<Part, (usize, usize)> = HashMap::new();
+    for (position, edit_changes) in changes.iter().enumerate() {
+        let mut lost: HashMap<usize, usize> = HashMap::new();
+        for (change_index, change) in edit_changes.iter().enumerate() {
+            let Some(change) = change else { continue };
+            match owners.entry(change.part()) {
+                Entry::Vacant(slot) => {
+                    slot.insert((position, change_index));
+                }
+                Entry::Occupied(slot) => {
+                    let (owner, owner_change) = *slot.get();
+                    if !changes[owner][owner_change].as_ref().is_some_and(|owned| owned.same_as(change)) {
+                        *lost.entry(owner).or_default() += 1;
+                    }
+                }
+            }
+        }
+        for (owner, shared) in lost {
+            warn_conflict(edits[owner].1, edits[position].1, shared);
+        }
+    }
+
+    let mut applied = Vec::new();
+    for (position, &(_, edit)) in edits.iter().enumerate() {
+        let mut kept = 0;
+        for change in &mut changes[position] {
+            let Some(part) = change.as_ref().map(Change::part) else { continue };
+            if owners.get(&part).is_some_and(|&(owner, _)| owner == position) {
+                apply(&mut composed, change.take().unwrap());
+                kept += 1;
+            }
+        }
+        if kept > 0 {
+            applied.push(edit.feature.name());
+        }
+    }
+    if applied.is_empty() {
+        log::warn!("No enabled feature changes anything in {}", ctx.path.display());
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(original.len());
+    if let Err(e) = composed.write(&mut output, version) {
+        refuse(ctx, &format!("writing the composed material failed: {e}"));
+        return None;
+    }
+    log::info!("Composed {} from {}", ctx.path.display(), applied.join(", "));
+    Some(output)
+}
+
+// Everything the edited material does differently from the game's. Variants and stages
+// the game's material doesn't have are left out, the game would never pick them
+fn diff(vanilla: &CompiledMaterialDefinition, edited: CompiledMaterialDefinition) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (name, field) in edited.property_field_definitions {
+        if vanilla.property_field_definitions.get(&name) != Some(&field) {
+            changes.push(Change::Uniform(name, field));
+        }
+    }
+    for (name, pass) in edited.passes {
+        let Some(vanilla_pass) = vanilla.passes.get(&name) else {
+            changes.push(Change::Pass(name, pass));
+            continue;
+        };
+        for variant in pass.variants {
+            let Some(variant_index) = vanilla_pass.variants.iter().position(|vanilla| vanilla.flags == variant.flags)
+            else {
+                continue;
+            };
+            let vanilla_stages = &vanilla_pass.variants[variant_index].shader_codes;
+            let mut stages = Vec::new();
+            for (stage, code) in variant.shader_codes {
+                let vanilla_code = vanilla_stages
+                    .iter()
+                    .find_map(|(vanilla_stage, vanilla_code)| (*vanilla_stage == stage).then_some(vanilla_code));
+                match vanilla_code {
+                    Some(vanilla_code) if vanilla_code.bgfx_shader_data != code.bgfx_shader_data => {
+                        stages.push((stage, code));
+                    }
+                    _ => {}
+                }
+            }
+            if !stages.is_empty() {
+                changes.push(Change::Variant {
+                    pass: name.clone(),
+                    variant: variant_index,
+                    stages,
+                });
+            }
+        }
+    }
+    changes
+}
+
+fn apply(material: &mut CompiledMaterialDefinition, change: Change) {
+    match change {
+        Change::Pass(name, pass) => {
+            material.passes.insert(name, pass);
+        }
+        Change::Variant { pass, variant, mut stages } => {
+            let Some(variant) = material
+                .passes
+                .get_mut(&pass)
+                .and_then(|pass| pass.variants.get_mut(variant))
+            else {
+                return;
+            };
+            for (stage, slot) in &mut variant.shader_codes {
+                if let Some(index) = stages.iter().position(|(changed, _)| changed == stage) {
+                    *slot = stages.swap_remove(index).1;
+                }
+            }
+        }
+        Change::Uniform(name, field) => {
+            material.property_field_definitions.insert(name, field);
+        }
+    }
+}
+
+fn refuse(ctx: &TransformContext, reason: &str) {
+    log::error!("Not replacing {}, {reason}", ctx.path.display());
+}
@@ -0,0 +1,47 @@
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, Write},
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+};
+
+/// An in-memory file holding data that can't be changed anymore, for callers
+/// that want a file descriptor instead of AAsset_read
+pub fn sealed_memfd(name: &CStr, data: &[u8]) -> io::Result<OwnedFd> {
+    // Through the syscall, bionic only exports memfd_create since API 30
+    let flags = libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING;
+    let fd = unsafe { libc::syscall(libc::SYS_memfd_create, name.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd as RawFd) };
+    file.write_all(data)?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file.into())
+}
+
+/// A new read-only fd for the caller to own and close, rewound to the start.
+/// It normally has its own file offset, but if /proc can't be reopened it's a dup
+/// sharing the offset with memfd and every other dup of it
+pub fn open_readonly(memfd: &OwnedFd) -> io::Result<RawFd> {
+    match File::open(format!("/proc/self/fd/{}", memfd.as_raw_fd())) {
+        Ok(file) => Ok(file.into_raw_fd()),
+        Err(e) => {
+            log::warn!("Reopening memfd failed, sharing its offset instead: {e}");
+            let fd = unsafe { libc::dup(memfd.as_raw_fd()) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // write_all left the shared offset at the end
+            if unsafe { libc::lseek(fd, 0, libc::SEEK_SET) } < 0 {
+                let e = io::Error::last_os_error();
+                unsafe { libc::close(fd) };
+                return Err(e);
+            }
+            Ok(fd)
+        }
+    }
+}
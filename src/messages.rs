@@ -0,0 +1,226 @@
+use crate::aasset::AssetData;
+use crate::config::{config_dir, MessageMode};
+use crate::json_patch::parse_lenient;
+use crate::rules::TransformContext;
+use serde_json::{Map, Value};
+use std::{
+    fs, io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// One splash per line, blank lines and lines starting with # are skipped
+const SPLASHES_FILE_NAME: &str = "splashes.txt";
+// Either one array for every category or an object of per-category arrays
+const LOADING_MESSAGES_FILE_NAME: &str = "loading_messages.json";
+
+// Served when the user hasn't provided their own messages
+const ORIGIN_MESSAGES: [&str; 10] = [
+    "Origin Client",
+    "Origin > any other client",
+    "The Best Client!!",
+    "BlueCat",
+    "Origin is so much better",
+    "Origin Optimizes like no other client",
+    "Make Sure to star our repository:https://github.com/Origin-Client/Origin",
+    "Contributions open!",
+    "Made by the community, for the community",
+    "Yami is goated!!",
+];
+
+// Used when the vanilla loading_messages.json can't be read
+const LOADING_MESSAGE_CATEGORIES: [&str; 8] = [
+    "beginner_loading_messages",
+    "mid_game_loading_messages",
+    "late_game_loading_messages",
+    "creative_loading_messages",
+    "editor_loading_messages",
+    "realms_loading_messages",
+    "addons_loading_messages",
+    "store_progress_tooltips",
+];
+
+pub fn splashes_transform(ctx: &TransformContext) -> Option<AssetData> {
+    let placeholders = Placeholders::new(ctx);
+    let mut splashes = read_original_object(ctx);
+    let vanilla = take_array(&mut splashes, "splashes");
+    let custom = placeholders.expand_all(load_splashes());
+    let combined = combine(ctx.config.splashes.mode, vanilla, Some(custom));
+    splashes.insert("splashes".into(), Value::Array(combined));
+    Some(Value::Object(splashes).to_string().into_bytes().into())
+}
+
+pub fn loading_messages_transform(ctx: &TransformContext) -> Option<AssetData> {
+    let config = &ctx.config.loading_messages;
+    let placeholders = Placeholders::new(ctx);
+    let mut messages = read_original_object(ctx);
+    let custom = load_loading_messages();
+
+    let mut categories: Vec<String> = messages
+        .iter()
+        .filter(|(_, value)| value.is_array())
+        .map(|(key, _)| key.clone())
+        .collect();
+    if categories.is_empty() {
+        categories.extend(LOADING_MESSAGE_CATEGORIES.map(String::from));
+    }
+    if let CustomMessages::PerCategory(per_category) = &custom {
+        for category in per_category.keys() {
+            if !categories.contains(category) {
+                categories.push(category.clone());
+            }
+        }
+    }
+
+    for category in categories {
+        let mode = config.categories.get(&category).copied().unwrap_or(config.mode);
+        let vanilla = take_array(&mut messages, &category);
+        let custom = custom
+            .for_category(&category)
+            .map(|custom| placeholders.expand_all(custom));
+        let combined = combine(mode, vanilla, custom);
+        messages.insert(category, Value::Array(combined));
+    }
+    Some(Value::Object(messages).to_string().into_bytes().into())
+}
+
+fn combine(mode: MessageMode, vanilla: Vec<Value>, custom: Option<Vec<String>>) -> Vec<Value> {
+    let custom = custom.map(|custom| custom.into_iter().map(Value::String));
+    match (mode, custom) {
+        (MessageMode::Disable, _) => Vec::new(),
+        // Nothing of ours for this category
+        (_, None) => vanilla,
+        (MessageMode::Replace, Some(custom)) => custom.collect(),
+        (MessageMode::Append, Some(custom)) => vanilla.into_iter().chain(custom).collect(),
+    }
+}
+
+fn read_original_object(ctx: &TransformContext) -> Map<String, Value> {
+    let original = ctx.read_original().and_then(|data| match parse_lenient(&data) {
+        Ok(Value::Object(object)) => Some(object),
+        Ok(_) => None,
+        Err(e) => {
+            log::warn!("Vanilla {} is not valid JSON: {e}", ctx.path.display());
+            None
+        }
+    });
+    original.unwrap_or_default()
+}
+
+fn take_array(object: &mut Map<String, Value>, key: &str) -> Vec<Value> {
+    match object.remove(key) {
+        Some(Value::Array(array)) => array,
+        _ => Vec::new(),
+    }
+}
+
+fn builtin_messages() -> Vec<String> {
+    ORIGIN_MESSAGES.map(String::from).to_vec()
+}
+
+fn read_config_file(name: &str) -> Option<String> {
+    let path = config_dir().join(name);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::warn!("Failed to read {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+fn load_splashes() -> Vec<String> {
+    let Some(contents) = read_config_file(SPLASHES_FILE_NAME) else {
+        return builtin_messages();
+    };
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+enum CustomMessages {
+    All(Vec<String>),
+    PerCategory(Map<String, Value>),
+}
+
+impl CustomMessages {
+    fn for_category(&self, category: &str) -> Option<Vec<String>> {
+        match self {
+            Self::All(messages) => Some(messages.clone()),
+            Self::PerCategory(per_category) => {
+                let messages = per_category.get(category)?.as_array()?;
+                Some(messages.iter().filter_map(Value::as_str).map(String::from).collect())
+            }
+        }
+    }
+}
+
+fn load_loading_messages() -> CustomMessages {
+    let Some(contents) = read_config_file(LOADING_MESSAGES_FILE_NAME) else {
+        return CustomMessages::All(builtin_messages());
+    };
+    match serde_json::from_str(&contents) {
+        Ok(Value::Object(per_category)) => CustomMessages::PerCategory(per_category),
+        Ok(Value::Array(messages)) => CustomMessages::All(
+            messages.iter().filter_map(Value::as_str).map(String::from).collect(),
+        ),
+        Ok(_) => {
+            log::warn!("{LOADING_MESSAGES_FILE_NAME} must be an array or an object, using built-in messages");
+            CustomMessages::All(builtin_messages())
+        }
+        Err(e) => {
+            log::warn!("Failed to parse {LOADING_MESSAGES_FILE_NAME}, using built-in messages: {e}");
+            CustomMessages::All(builtin_messages())
+        }
+    }
+}
+
+struct Placeholders {
+    mc_version: String,
+    date: String,
+}
+
+impl Placeholders {
+    fn new(ctx: &TransformContext) -> Self {
+        let mc_version = match crate::aasset::mc_version(ctx.manager) {
+            Some(version) => version.to_string(),
+            None => "unknown".into(),
+        };
+        Self {
+            mc_version,
+            date: today_utc(),
+        }
+    }
+
+    fn expand_all(&self, messages: Vec<String>) -> Vec<String> {
+        messages
+            .into_iter()
+            .map(|message| {
+                message
+                    .replace("{mc_version}", &self.mc_version)
+                    .replace("{date}", &self.date)
+            })
+            .collect()
+    }
+}
+
+// YYYY-MM-DD, using the days-to-civil conversion from Howard Hinnant's date algorithms
+fn today_utc() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,127 @@
+use crate::config::config_dir;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs, io,
+    os::{fd::RawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+// Mirrors APK asset paths, e.g. overrides/resource_packs/vanilla/textures/environment/clouds.png
+pub const OVERRIDES_DIR_NAME: &str = "overrides";
+
+// APK path (without the "assets/" prefix) -> file on disk
+static OVERRIDES: Lazy<RwLock<HashMap<Vec<u8>, PathBuf>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+pub fn init_overrides() {
+    reindex_overrides();
+}
+
+/// Walks the overrides dir once so lookups from open never touch the filesystem
+pub fn reindex_overrides() {
+    let root = config_dir().join(OVERRIDES_DIR_NAME);
+    let mut index = HashMap::new();
+    match index_dir(&root, &root, &mut index) {
+        Ok(()) => log::info!("Indexed {} asset overrides in {}", index.len(), root.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to index {}: {}", root.display(), e),
+    }
+    *OVERRIDES.write().unwrap() = index;
+}
+
+/// Adds inotify watches for the overrides dir and every dir under it, so the config
+/// watcher can reindex when files change. Dirs that are already watched keep their watch
+pub fn watch_overrides(inotify: RawFd) {
+    let root = config_dir().join(OVERRIDES_DIR_NAME);
+    match watch_dir(inotify, &root) {
+        Ok(()) => {}
+        // The config watcher notices the dir being created
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to watch {}, new overrides need a config save: {}", root.display(), e),
+    }
+}
+
+fn watch_dir(inotify: RawFd, dir: &Path) -> io::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mask = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_TO
+        | libc::IN_MOVED_FROM
+        | libc::IN_ONLYDIR;
+    if unsafe { libc::inotify_add_watch(inotify, path.as_ptr(), mask) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if let Err(e) = watch_dir(inotify, &entry.path()) {
+                log::warn!("Failed to watch {}: {}", entry.path().display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn index_dir(root: &Path, dir: &Path, index: &mut HashMap<Vec<u8>, PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if let Err(e) = index_dir(root, &path, index) {
+                log::warn!("Failed to index {}: {}", path.display(), e);
+            }
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let key = strip_assets_prefix(relative.as_os_str().as_bytes()).to_vec();
+        index.insert(key, path);
+    }
+    Ok(())
+}
+
+// Newer versions open "assets/<path>", older ones just "<path>"
+fn strip_assets_prefix(path: &[u8]) -> &[u8] {
+    path.strip_prefix(b"assets/").unwrap_or(path)
+}
+
+/// Returns the file shadowing this APK path, if the user provided one
+pub fn find_override(path: &[u8]) -> Option<PathBuf> {
+    let overrides = OVERRIDES.read().unwrap();
+    if overrides.is_empty() {
+        return None;
+    }
+    overrides.get(strip_assets_prefix(path)).cloned()
+}
+
+/// Names of the override files directly inside an APK dir, for directory listings
+pub fn files_in_dir(dir: &[u8]) -> Vec<CString> {
+    let dir = match dir {
+        b"assets" | b"assets/" => b"",
+        dir => strip_assets_prefix(dir),
+    };
+    let dir = dir.strip_suffix(b"/").unwrap_or(dir);
+    let overrides = OVERRIDES.read().unwrap();
+    let mut names: Vec<CString> = overrides
+        .keys()
+        .filter_map(|path| {
+            let name = match dir.is_empty() {
+                true => path.as_slice(),
+                false => path.strip_prefix(dir)?.strip_prefix(b"/")?,
+            };
+            if name.contains(&b'/') {
+                return None;
+            }
+            CString::new(name).ok()
+        })
+        .collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,124 @@
+use crate::aasset::AssetData;
+use crate::config::ParticlesDisablerConfig;
+use crate::json_patch::parse_lenient;
+use crate::rules::TransformContext;
+use serde_json::{Map, Number, Value};
+
+// Fields scaled in every minecraft:emitter_rate_* component
+const EMITTER_RATE_FIELDS: [&str; 3] = ["num_particles", "spawn_rate", "max_particles"];
+// Fields scaled in every minecraft:particle_lifetime_* component
+const PARTICLE_LIFETIME_FIELDS: [&str; 1] = ["max_lifetime"];
+
+/// Serves a particles/*.json effect with fewer, shorter lived particles,
+/// anything that isn't a particle effect with an identifier is left alone
+pub fn particle_transform(ctx: &TransformContext) -> Option<AssetData> {
+    let config = &ctx.config.particles_disabler;
+    if ctx.path.extension().is_none_or(|extension| extension != "json") {
+        return None;
+    }
+    let density = config.density.max(0.0);
+    if density == 1.0 {
+        return None;
+    }
+    let original = ctx.read_original()?;
+    let mut effect = match parse_lenient(&original) {
+        Ok(effect) => effect,
+        Err(e) => {
+            log::warn!("Not scaling {}, it is not valid JSON: {e}", ctx.path.display());
+            return None;
+        }
+    };
+    if !is_selected(config, effect_identifier(&effect)?) {
+        return None;
+    }
+    let components = effect
+        .get_mut("particle_effect")?
+        .get_mut("components")?
+        .as_object_mut()?;
+    scale_components(components, density);
+    Some(effect.to_string().into_bytes().into())
+}
+
+fn effect_identifier(effect: &Value) -> Option<&str> {
+    effect
+        .get("particle_effect")?
+        .get("description")?
+        .get("identifier")?
+        .as_str()
+}
+
+fn is_selected(config: &ParticlesDisablerConfig, identifier: &str) -> bool {
+    let listed = |list: &[String]| list.iter().any(|pattern| identifier_matches(pattern, identifier));
+    if listed(&config.keep) {
+        return false;
+    }
+    config.drop.is_empty() || listed(&config.drop)
+}
+
+fn identifier_matches(pattern: &str, identifier: &str) -> bool {
+    let pattern = pattern.trim();
+    // Vanilla effects are all namespaced, so "crit*" means "minecraft:crit*"
+    let namespaced;
+    let pattern = if pattern.contains(':') {
+        pattern
+    } else {
+        namespaced = format!("minecraft:{pattern}");
+        &namespaced
+    };
+    match pattern.strip_suffix('*') {
+        Some(prefix) => identifier.starts_with(prefix),
+        None => identifier == pattern,
+    }
+}
+
+fn scale_components(components: &mut Map<String, Value>, density: f64) {
+    for (name, component) in components.iter_mut() {
+        // Emitter rates are particle counts, lifetimes are seconds
+        let (fields, counts): (&[&str], bool) = if name.starts_with("minecraft:emitter_rate_") {
+            (&EMITTER_RATE_FIELDS, true)
+        } else if name.starts_with("minecraft:particle_lifetime_") {
+            (&PARTICLE_LIFETIME_FIELDS, false)
+        } else {
+            continue;
+        };
+        let Some(component) = component.as_object_mut() else {
+            continue;
+        };
+        for field in fields {
+            if let Some(value) = component.get_mut(*field) {
+                scale_value(value, density, counts);
+            }
+        }
+    }
+}
+
+// Numbers are scaled in place, Molang expressions get wrapped in a multiplication
+fn scale_value(value: &mut Value, density: f64, counts: bool) {
+    match value {
+        Value::Number(number) => {
+            let scaled = match number.as_u64() {
+                // An emitter that spawned something keeps spawning at least one
+                Some(count) if counts => {
+                    let mut scaled = (count as f64 * density).round() as u64;
+                    if count > 0 && density > 0.0 {
+                        scaled = scaled.max(1);
+                    }
+                    Number::from(scaled)
+                }
+                _ => match number.as_f64().and_then(|n| Number::from_f64(n * density)) {
+                    Some(scaled) => scaled,
+                    None => return,
+                },
+            };
+            *number = scaled;
+        }
+        // Wrapping only works for a single expression, not statements ending in return
+        Value::String(expression) if expression.contains(';') || expression.contains("return") => {
+            log::warn!("Not scaling multi-statement Molang: {expression}");
+        }
+        Value::String(expression) if !expression.trim().is_empty() => {
+            *expression = format!("({expression}) * {density}");
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,588 @@
+use crate::aasset::AssetData;
+use crate::bundle::BundledAsset;
+use crate::config::{config_dir, get_config, ModConfig, NoFogMode};
+use crate::json_patch::{parse_lenient, JsonPatch, Operation};
+use aho_corasick::AhoCorasick;
+use ndk_sys::{AAsset, AAssetManager};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+pub const RULES_FILE_NAME: &str = "rules.json";
+
+// User rules come first so they can shadow the built-in ones
+static RULES: Lazy<RwLock<Arc<RuleSet>>> = Lazy::new(|| {
+    let empty = RuleSet::compile(Arc::new(Vec::new()), &ModConfig::default());
+    RwLock::new(Arc::new(empty))
+});
+
+/// How a rule matches the path the game passed to AAssetManager_open
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathPattern {
+    Exact(String),
+    // Last path component only
+    FileName(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+}
+
+impl PathPattern {
+    // The literal the matcher searches for and where in the path it has to be found
+    fn needle(&self) -> (&str, Anchor) {
+        match self {
+            Self::Exact(pattern) => (pattern, Anchor::Whole),
+            Self::FileName(pattern) => (pattern, Anchor::FileName),
+            Self::Prefix(pattern) => (pattern, Anchor::Start),
+            Self::Suffix(pattern) => (pattern, Anchor::End),
+            Self::Contains(pattern) => (pattern, Anchor::Anywhere),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Anchor {
+    Whole,
+    // Ends the path right after a '/' or the start of it
+    FileName,
+    Start,
+    End,
+    Anywhere,
+}
+
+impl Anchor {
+    fn accepts(self, path: &[u8], start: usize, end: usize) -> bool {
+        match self {
+            Self::Whole => start == 0 && end == path.len(),
+            Self::FileName => end == path.len() && (start == 0 || path[start - 1] == b'/'),
+            Self::Start => start == 0,
+            Self::End => end == path.len(),
+            Self::Anywhere => true,
+        }
+    }
+}
+
+/// Config toggle a rule is gated behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    NoHurtCam,
+    NoFog,
+    // no_fog categories, each also needs no_fog in materials mode
+    NoFogTerrain,
+    NoFogActors,
+    NoFogHeldItems,
+    NoFogParticles,
+    NoFogSky,
+    // no_fog in fog_definitions mode
+    NoFogDefinitions,
+    ParticlesDisabler,
+    JavaClouds,
+    JavaCubemap,
+    ClassicSkins,
+    CapePhysics,
+    NightVision,
+    Splashes,
+    LoadingMessages,
+}
+
+impl Feature {
+    pub fn is_enabled(self, config: &ModConfig) -> bool {
+        match self {
+            Self::NoHurtCam => config.no_hurt_cam.enabled,
+            Self::NoFog => config.no_fog.enabled,
+            Self::NoFogTerrain => config.no_fog.uses(NoFogMode::Materials) && config.no_fog.terrain,
+            Self::NoFogActors => config.no_fog.uses(NoFogMode::Materials) && config.no_fog.actors,
+            Self::NoFogHeldItems => config.no_fog.uses(NoFogMode::Materials) && config.no_fog.held_items,
+            Self::NoFogParticles => config.no_fog.uses(NoFogMode::Materials) && config.no_fog.particles,
+            Self::NoFogSky => config.no_fog.uses(NoFogMode::Materials) && config.no_fog.sky,
+            Self::NoFogDefinitions => config.no_fog.uses(NoFogMode::FogDefinitions),
+            Self::ParticlesDisabler => config.particles_disabler.enabled,
+            Self::JavaClouds => config.java_clouds.enabled,
+            Self::JavaCubemap => config.java_cubemap.enabled,
+            Self::ClassicSkins => config.classic_skins.enabled,
+            Self::CapePhysics => config.cape_physics.enabled,
+            Self::NightVision => config.night_vision.enabled,
+            Self::Splashes => config.splashes.enabled,
+            Self::LoadingMessages => config.loading_messages.enabled,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NoHurtCam => "no_hurt_cam",
+            Self::NoFog => "no_fog",
+            Self::NoFogTerrain => "no_fog_terrain",
+            Self::NoFogActors => "no_fog_actors",
+            Self::NoFogHeldItems => "no_fog_held_items",
+            Self::NoFogParticles => "no_fog_particles",
+            Self::NoFogSky => "no_fog_sky",
+            Self::NoFogDefinitions => "no_fog_definitions",
+            Self::ParticlesDisabler => "particles_disabler",
+            Self::JavaClouds => "java_clouds",
+            Self::JavaCubemap => "java_cubemap",
+            Self::ClassicSkins => "classic_skins",
+            Self::CapePhysics => "cape_physics",
+            Self::NightVision => "night_vision",
+            Self::Splashes => "splashes",
+            Self::LoadingMessages => "loading_messages",
+        }
+    }
+}
+
+/// Everything a transform gets to build its replacement from
+pub struct TransformContext<'a> {
+    pub config: &'a ModConfig,
+    pub path: &'a Path,
+    pub manager: *mut AAssetManager,
+    // Null when the APK doesn't have this asset
+    pub asset: *mut AAsset,
+}
+
+impl TransformContext<'_> {
+    /// The bytes the APK has for this asset, read through the real AAsset_read
+    pub fn read_original(&self) -> Option<Vec<u8>> {
+        unsafe { crate::aasset::read_original(self.asset) }
+    }
+
+    /// Applies a patch to the original asset, None if it isn't JSON or the patch fails
+    pub fn patch_original(&self, patch: &JsonPatch) -> Option<Vec<u8>> {
+        let original = self.read_original()?;
+        let mut json = match parse_lenient(&original) {
+            Ok(json) => json,
+            Err(e) => {
+                log::warn!("Can't patch {}, original is not valid JSON: {e}", self.path.display());
+                return None;
+            }
+        };
+        if let Err(e) = patch.apply(&mut json) {
+            log::warn!("Failed to patch {}: {e}", self.path.display());
+            return None;
+        }
+        Some(json.to_string().into_bytes())
+    }
+}
+
+/// Returns the bytes to serve, or None to let the game have the original
+pub type TransformFn = fn(&TransformContext) -> Option<AssetData>;
+
+pub enum RuleAction {
+    // Built-in asset from the bundle
+    Replace(BundledAsset),
+    // Read from disk on every open so edits show up without a restart
+    ReplaceFile(PathBuf),
+    Block,
+    Redirect(CString),
+    // Applied on top of the original JSON so fields from newer versions survive
+    Patch(JsonPatch),
+    Transform(TransformFn),
+}
+
+pub struct Rule {
+    pub pattern: PathPattern,
+    // None means the rule is always active
+    pub feature: Option<Feature>,
+    pub action: RuleAction,
+    // Came from rules.json rather than a built-in feature
+    pub user: bool,
+}
+
+impl Rule {
+    pub fn new(pattern: PathPattern, feature: Option<Feature>, action: RuleAction) -> Self {
+        Self {
+            pattern,
+            feature,
+            action,
+            user: false,
+        }
+    }
+
+    /// What to blame in logs for this rule firing
+    pub fn label(&self) -> &'static str {
+        match (self.user, self.feature) {
+            (true, _) => RULES_FILE_NAME,
+            (false, Some(feature)) => feature.name(),
+            (false, None) => "builtin",
+        }
+    }
+}
+
+/// Every rule, plus one automaton over the patterns of those active under the
+/// config it was compiled for, so open does a single pass over the path
+pub struct RuleSet {
+    rules: Arc<Vec<Rule>>,
+    // None when no rule is active
+    matcher: Option<AhoCorasick>,
+    // Automaton pattern id -> active rules searching for that literal, in priority order
+    candidates: Vec<Vec<(usize, Anchor)>>,
+}
+
+impl RuleSet {
+    fn compile(rules: Arc<Vec<Rule>>, config: &ModConfig) -> Self {
+        let mut needles: Vec<&str> = Vec::new();
+        let mut candidates: Vec<Vec<(usize, Anchor)>> = Vec::new();
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        for (index, rule) in rules.iter().enumerate() {
+            if !rule.feature.is_none_or(|feature| feature.is_enabled(config)) {
+                continue;
+            }
+            let (needle, anchor) = rule.pattern.needle();
+            let id = *ids.entry(needle).or_insert_with(|| {
+                needles.push(needle);
+                candidates.push(Vec::new());
+                needles.len() - 1
+            });
+            candidates[id].push((index, anchor));
+        }
+        let matcher = match needles.is_empty() {
+            true => None,
+            false => match AhoCorasick::new(&needles) {
+                Ok(matcher) => Some(matcher),
+                Err(e) => {
+                    log::error!("Failed to compile asset rules, none will apply: {e}");
+                    None
+                }
+            },
+        };
+        let active = candidates.iter().map(Vec::len).sum::<usize>();
+        log::info!("Compiled {active} of {} asset rules", rules.len());
+        Self {
+            rules,
+            matcher,
+            candidates,
+        }
+    }
+
+    /// The first active rule matching the path, earlier rules win
+    pub fn find(&self, path: &[u8]) -> Option<&Rule> {
+        let matcher = self.matcher.as_ref()?;
+        let mut best: Option<usize> = None;
+        for found in matcher.find_overlapping_iter(path) {
+            for &(index, anchor) in &self.candidates[found.pattern().as_usize()] {
+                if best.is_some_and(|best| best <= index) {
+                    break;
+                }
+                if anchor.accepts(path, found.start(), found.end()) {
+                    best = Some(index);
+                    break;
+                }
+            }
+        }
+        best.map(|index| &self.rules[index])
+    }
+}
+
+pub fn current_rules() -> Arc<RuleSet> {
+    RULES.read().unwrap().clone()
+}
+
+pub fn init_rules() {
+    reload_rules();
+}
+
+/// Rebuilds the rule table from rules.json and the built-in features
+pub fn reload_rules() {
+    let mut rules = load_user_rules();
+    let user_count = rules.len();
+    rules.extend(crate::aasset::builtin_rules());
+    log::info!("Loaded {} asset rules ({} from {})", rules.len(), user_count, RULES_FILE_NAME);
+    let rule_set = RuleSet::compile(Arc::new(rules), &get_config());
+    *RULES.write().unwrap() = Arc::new(rule_set);
+}
+
+/// Recompiles the matcher after the config changed which features are on
+pub fn recompile_rules() {
+    let rules = current_rules().rules.clone();
+    let rule_set = RuleSet::compile(rules, &get_config());
+    *RULES.write().unwrap() = Arc::new(rule_set);
+}
+
+// The shape of one entry in rules.json, e.g.
+// { "match": { "suffix": "environment/sun.png" }, "feature": "java_clouds", "action": { "replace": "sun.png" } }
+#[derive(Deserialize)]
+struct UserRule {
+    #[serde(rename = "match")]
+    pattern: PathPattern,
+    #[serde(default)]
+    feature: Option<Feature>,
+    action: UserAction,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UserAction {
+    // Path relative to the config dir
+    Replace(PathBuf),
+    Block,
+    // Another APK asset path
+    Redirect(String),
+    MergePatch(Value),
+    JsonPatch(Vec<Operation>),
+}
+
+fn load_user_rules() -> Vec<Rule> {
+    let path = config_dir().join(RULES_FILE_NAME);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            log::warn!("Failed to read {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let user_rules: Vec<UserRule> = match serde_json::from_str(&contents) {
+        Ok(rules) => rules,
+        Err(e) => {
+            log::warn!("Failed to parse {}, ignoring it: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    user_rules
+        .into_iter()
+        .filter_map(|user_rule| {
+            let action = match user_rule.action {
+                UserAction::Replace(file) => RuleAction::ReplaceFile(config_dir().join(file)),
+                UserAction::Block => RuleAction::Block,
+                UserAction::Redirect(target) => match CString::new(target) {
+                    Ok(target) => RuleAction::Redirect(target),
+                    Err(e) => {
+                        log::warn!("Skipping rule with invalid redirect target: {}", e);
+                        return None;
+                    }
+                },
+                UserAction::MergePatch(patch) => RuleAction::Patch(JsonPatch::MergePatch(patch)),
+                UserAction::JsonPatch(operations) => {
+                    RuleAction::Patch(JsonPatch::JsonPatch(operations))
+                }
+            };
+            Some(Rule {
+                pattern: user_rule.pattern,
+                feature: user_rule.feature,
+                action,
+                user: true,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NoFogMode;
+
+    // Rules are told apart by their redirect target
+    fn rule(pattern: PathPattern, id: &str) -> Rule {
+        Rule::new(
+            pattern,
+            None,
+            RuleAction::Redirect(CString::new(id).unwrap()),
+        )
+    }
+
+    fn find(rules: Vec<Rule>, config: &ModConfig, path: &str) -> Option<String> {
+        let rule_set = RuleSet::compile(Arc::new(rules), config);
+        let rule = rule_set.find(path.as_bytes())?;
+        match &rule.action {
+            RuleAction::Redirect(id) => Some(id.to_str().unwrap().to_owned()),
+            _ => Some(rule.label().to_owned()),
+        }
+    }
+
+    fn find_one(pattern: PathPattern, path: &str) -> bool {
+        find(vec![rule(pattern, "rule")], &ModConfig::default(), path).is_some()
+    }
+
+    #[test]
+    fn anchors() {
+        let exact = || PathPattern::Exact("a/b.json".into());
+        assert!(find_one(exact(), "a/b.json"));
+        assert!(!find_one(exact(), "x/a/b.json"));
+        assert!(!find_one(exact(), "a/b.json.bak"));
+
+        let file_name = || PathPattern::FileName("clouds.png".into());
+        assert!(find_one(file_name(), "clouds.png"));
+        assert!(find_one(file_name(), "textures/environment/clouds.png"));
+        assert!(!find_one(
+            file_name(),
+            "textures/environment/java_clouds.png"
+        ));
+        assert!(!find_one(file_name(), "clouds.png/readme.txt"));
+
+        let prefix = || PathPattern::Prefix("assets/".into());
+        assert!(find_one(prefix(), "assets/a.json"));
+        assert!(!find_one(prefix(), "x/assets/a.json"));
+
+        let suffix = || PathPattern::Suffix("vanilla/steve.png".into());
+        assert!(find_one(suffix(), "skin_packs/vanilla/steve.png"));
+        assert!(!find_one(suffix(), "skin_packs/vanilla/steve.png.bak"));
+
+        let contains = || PathPattern::Contains("/particles/".into());
+        assert!(find_one(
+            contains(),
+            "resource_packs/vanilla/particles/crit.json"
+        ));
+        assert!(!find_one(contains(), "particles/crit.json"));
+    }
+
+    #[test]
+    fn earlier_rule_wins_over_earlier_match() {
+        let path = "resource_packs/vanilla/particles/crit.json";
+        let config = ModConfig::default();
+        let rules = || {
+            vec![
+                rule(PathPattern::Suffix("crit.json".into()), "suffix"),
+                rule(PathPattern::Prefix("resource_packs/".into()), "prefix"),
+            ]
+        };
+        assert_eq!(find(rules(), &config, path).as_deref(), Some("suffix"));
+        let mut reversed = rules();
+        reversed.reverse();
+        assert_eq!(find(reversed, &config, path).as_deref(), Some("prefix"));
+    }
+
+    #[test]
+    fn shared_needle_falls_through_to_next_anchor() {
+        let config = ModConfig::default();
+        let rules = || {
+            vec![
+                rule(PathPattern::FileName("skins.json".into()), "file_name"),
+                rule(PathPattern::Suffix("skins.json".into()), "suffix"),
+                rule(PathPattern::Contains("skins.json".into()), "contains"),
+            ]
+        };
+        assert_eq!(
+            find(rules(), &config, "vanilla/skins.json").as_deref(),
+            Some("file_name")
+        );
+        assert_eq!(
+            find(rules(), &config, "vanilla/old_skins.json").as_deref(),
+            Some("suffix")
+        );
+        assert_eq!(
+            find(rules(), &config, "vanilla/skins.json.bak").as_deref(),
+            Some("contains")
+        );
+        assert_eq!(find(rules(), &config, "vanilla/skin.json"), None);
+    }
+
+    #[test]
+    fn rules_of_disabled_features_are_skipped() {
+        let mut config = ModConfig::default();
+        let rules = || {
+            vec![
+                Rule::new(
+                    PathPattern::FileName("clouds.png".into()),
+                    Some(Feature::JavaClouds),
+                    RuleAction::Block,
+                ),
+                rule(PathPattern::FileName("clouds.png".into()), "always"),
+            ]
+        };
+        assert_eq!(
+            find(rules(), &config, "environment/clouds.png").as_deref(),
+            Some("always")
+        );
+        config.java_clouds.enabled = true;
+        assert_eq!(
+            find(rules(), &config, "environment/clouds.png").as_deref(),
+            Some("java_clouds")
+        );
+    }
+
+    #[test]
+    fn builtin_rules() {
+        let mut config = ModConfig::default();
+        config.no_hurt_cam.enabled = true;
+        config.no_fog.enabled = true;
+        config.particles_disabler.enabled = true;
+        config.java_clouds.enabled = true;
+        config.java_cubemap.enabled = true;
+        config.classic_skins.enabled = true;
+        config.cape_physics.enabled = true;
+        config.night_vision.enabled = true;
+        config.splashes.enabled = true;
+        config.loading_messages.enabled = true;
+        let find_builtin =
+            |config: &ModConfig, path: &str| find(crate::aasset::builtin_rules(), config, path);
+
+        for (path, label) in [
+            (
+                "skin_packs/persona/08_Kai_Dcast.json",
+                Some("classic_skins"),
+            ),
+            (
+                "resource_packs/vanilla/particles/crit.json",
+                Some("particles_disabler"),
+            ),
+            ("resource_packs/vanilla/splashes.json", Some("splashes")),
+            (
+                "resource_packs/vanilla/loading_messages.json",
+                Some("loading_messages"),
+            ),
+            (
+                "resource_packs/vanilla/textures/environment/clouds.png",
+                Some("java_clouds"),
+            ),
+            ("skin_packs/vanilla/steve.png", Some("classic_skins")),
+            ("resource_packs/vanilla/textures/entity/alex.png", None),
+            ("skin_packs/vanilla/skins.json", Some("classic_skins")),
+            (
+                "resource_packs/vanilla/cameras/third_person_front.json",
+                Some("no_hurt_cam"),
+            ),
+            ("resource_packs/vanilla/third_person.json", None),
+            // Both features edit RenderChunk, the first edit's rule matches for them
+            (
+                "renderer/materials/RenderChunk.material.bin",
+                Some("no_fog_terrain"),
+            ),
+            (
+                "renderer/materials/LegacyCubemap.material.bin",
+                Some("java_cubemap"),
+            ),
+            (
+                "renderer/materials/ActorBanner.material.bin",
+                Some("no_fog_actors"),
+            ),
+            (
+                "renderer/materials/Particle.material.bin",
+                Some("no_fog_particles"),
+            ),
+            ("resource_packs/vanilla/fogs/default_fog_setting.json", None),
+            (
+                "resource_packs/vanilla/animations/player.animation.json",
+                Some("cape_physics"),
+            ),
+            (
+                "resource_packs/vanilla/entity/mobs.json",
+                Some("cape_physics"),
+            ),
+        ] {
+            assert_eq!(find_builtin(&config, path).as_deref(), label, "{path}");
+        }
+
+        config.no_fog.mode = NoFogMode::FogDefinitions;
+        config.night_vision.enabled = false;
+        for (path, label) in [
+            (
+                "resource_packs/vanilla/fogs/default_fog_setting.json",
+                Some("no_fog_definitions"),
+            ),
+            (
+                "resource_packs/vanilla/biomes_client.json",
+                Some("no_fog_definitions"),
+            ),
+            ("renderer/materials/RenderChunk.material.bin", None),
+        ] {
+            assert_eq!(find_builtin(&config, path).as_deref(), label, "{path}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::config::{config_dir, TraceConfig};
+use once_cell::sync::Lazy;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// One tab separated line per AAssetManager_open:
+// timestamp, mode, exists/missing, intercepted/passed, handler, path
+pub const TRACE_FILE_NAME: &str = "asset_trace.log";
+
+struct TraceLog {
+    file: File,
+    size: u64,
+}
+
+struct TraceState {
+    // Opened on the first traced asset
+    log: Option<TraceLog>,
+    // Only the first of a run of write errors gets logged
+    failing: bool,
+}
+
+static TRACE: Lazy<Mutex<TraceState>> = Lazy::new(|| {
+    Mutex::new(TraceState {
+        log: None,
+        failing: false,
+    })
+});
+
+/// Appends one open request to the trace log, handler is None if nothing intercepted it
+pub fn record(
+    config: &TraceConfig,
+    path: &[u8],
+    mode: libc::c_int,
+    existed: bool,
+    handler: Option<&str>,
+) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let line = format!(
+        "{}.{:03}\t{}\t{}\t{}\t{}\t{}\n",
+        timestamp.as_secs(),
+        timestamp.subsec_millis(),
+        mode_name(mode),
+        if existed { "exists" } else { "missing" },
+        if handler.is_some() { "intercepted" } else { "passed" },
+        handler.unwrap_or("-"),
+        String::from_utf8_lossy(path),
+    );
+    let mut trace = TRACE.lock().unwrap();
+    match write_line(&mut trace, config, line.as_bytes()) {
+        Ok(()) => trace.failing = false,
+        Err(e) => {
+            if !trace.failing {
+                log::warn!("Failed to write {TRACE_FILE_NAME}: {e}");
+            }
+            trace.failing = true;
+            trace.log = None;
+        }
+    }
+}
+
+fn mode_name(mode: libc::c_int) -> &'static str {
+    // AASSET_MODE_* from android/asset_manager.h
+    match mode {
+        0 => "unknown",
+        1 => "random",
+        2 => "streaming",
+        3 => "buffer",
+        _ => "invalid",
+    }
+}
+
+fn write_line(trace: &mut TraceState, config: &TraceConfig, line: &[u8]) -> io::Result<()> {
+    let max_size = config.max_size_kib.saturating_mul(1024);
+    let log = match &mut trace.log {
+        Some(log) => log,
+        None => trace.log.insert(open_log()?),
+    };
+    if log.size > 0 && log.size + line.len() as u64 > max_size {
+        rotate(config.max_files)?;
+        *log = open_log()?;
+    }
+    log.file.write_all(line)?;
+    log.size += line.len() as u64;
+    Ok(())
+}
+
+fn open_log() -> io::Result<TraceLog> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path(0))?;
+    let size = file.metadata()?.len();
+    Ok(TraceLog { file, size })
+}
+
+// 0 is the current log, higher numbers are older
+fn log_path(index: u32) -> PathBuf {
+    match index {
+        0 => config_dir().join(TRACE_FILE_NAME),
+        index => config_dir().join(format!("{TRACE_FILE_NAME}.{index}")),
+    }
+}
+
+// Shifts every log up by one, the oldest one gets overwritten
+fn rotate(max_files: u32) -> io::Result<()> {
+    if max_files == 0 {
+        return fs::remove_file(log_path(0));
+    }
+    for index in (0..max_files).rev() {
+        match fs::rename(log_path(index), log_path(index + 1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}